@@ -0,0 +1,114 @@
+//! Cache of the values of subtrees over the rows being scored.
+//! Crossover and copying mean most of the subtrees in a new
+//! generation were in the last one.  A subtree is keyed by a hash of
+//! its operator and the keys of its children and holds its value for
+//! every row, as evaluated a column at a time (see
+//! `Node::evaluate_columns_cached`).
+//!
+//! The values are only good for the rows and policy they were
+//! evaluated for, so the cache is cleared if either changes
+
+use node::Policy;
+use std::collections::HashMap;
+use std::mem;
+
+pub struct Cache {
+    // The subtree values and the generation each was last used in
+    values:HashMap<u64, (Vec<f64>, usize)>,
+
+    // Most bytes the values can use.  Zero turns the cache off
+    budget:usize,
+
+    // Bytes the values use now
+    size:usize,
+
+    // Bumped every generation.  Values not used in this generation
+    // are dropped when the cache is full
+    generation:usize,
+
+    // What the values are for
+    rows:Vec<usize>,
+    policy:Option<Policy>,
+
+    pub hits:usize,
+    pub misses:usize,
+}
+
+impl Cache {
+    /// A cache that can hold `budget` bytes of values
+    pub fn new(budget:usize) -> Cache {
+        Cache{
+            values:HashMap::new(),
+            budget:budget,
+            size:0,
+            generation:0,
+            rows:Vec::new(),
+            policy:None,
+            hits:0,
+            misses:0,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.budget > 0
+    }
+
+    pub fn clear(&mut self) {
+        self.values.clear();
+        self.size = 0;
+    }
+
+    /// Values found from now on are from a new generation
+    pub fn new_generation(&mut self) {
+        self.generation += 1;
+        self.hits = 0;
+        self.misses = 0;
+    }
+
+    /// Values will be for these rows evaluated with this policy.
+    /// Keep the values already found if they are for the same
+    pub fn prepare(&mut self, rows:&[usize], policy:Policy) {
+        if self.policy != Some(policy) || &self.rows[..] != rows {
+            self.clear();
+            self.rows = rows.to_vec();
+            self.policy = Some(policy);
+        }
+    }
+
+    pub fn get(&mut self, key:u64) -> Option<Vec<f64>> {
+        let generation = self.generation;
+        match self.values.get_mut(&key) {
+            Some(v) => {
+                v.1 = generation;
+                self.hits += 1;
+                Some(v.0.clone())
+            },
+            None => {
+                self.misses += 1;
+                None
+            },
+        }
+    }
+
+    /// Store the value of a subtree if there is room.  Make room by
+    /// dropping values not used in this generation
+    pub fn insert(&mut self, key:u64, value:&[f64]) {
+        let sz = Cache::bytes(value.len());
+        if self.size + sz > self.budget {
+            let generation = self.generation;
+            self.values.retain(|_, v| v.1 == generation);
+            self.size = self.values.values().map(|v| {
+                Cache::bytes(v.0.len())
+            }).sum();
+        }
+        if self.size + sz <= self.budget {
+            self.size += sz;
+            self.values.insert(key, (value.to_vec(), self.generation));
+        }
+    }
+
+    // The memory used by a value and its key
+    fn bytes(n:usize) -> usize {
+        mem::size_of::<u64>() + n * mem::size_of::<f64>()
+    }
+}
@@ -0,0 +1,141 @@
+//! Trees compiled to postfix instructions for a stack machine.
+//! Evaluating a `Box<Node>` walks the tree and looks up every input
+//! by name.  A compiled tree is a flat list of instructions with the
+//! inputs resolved to columns of a row of `Data::data`.  See
+//! `Node::compile`
+
+use node::EvalError;
+use node::Operator;
+use node::Policy;
+use node::TerminalType;
+
+#[derive(Debug, Clone)]
+pub enum Instruction {
+    // Push a constant
+    Const(f64),
+
+    // Push the value in this column of the row
+    Input(usize),
+
+    // Push an argument to the ADF being evaluated
+    Arg(usize),
+
+    // Pop the argument, push the value of the operator
+    Unary(Operator),
+
+    // Pop `r` then `l`, push the value of the operator
+    Binary(Operator),
+
+    // Pop the arguments (the second value is how many) and push the
+    // value of the ADF with this index
+    Call(usize, usize),
+
+    // Pop the condition of an `If`.  Jump to the instruction if it is
+    // not positive
+    Branch(usize),
+
+    // Jump to the instruction
+    Jump(usize),
+
+    // Fail the evaluation where the interpreter would
+    Fail(EvalError),
+}
+
+#[derive(Debug, Clone)]
+pub struct Compiled {
+    // The code of each ADF.  An ADF can only call those before it
+    functions:Vec<Vec<Instruction>>,
+
+    // The code of the result producing branch
+    code:Vec<Instruction>,
+
+    // The input columns, to name an input in an error
+    names:Vec<String>,
+
+    // The root of the result producing branch, to name it when the
+    // value is not finite
+    root:String,
+}
+
+impl Compiled {
+    pub fn new(functions:Vec<Vec<Instruction>>,
+               code:Vec<Instruction>,
+               names:&[String],
+               root:&Operator) -> Compiled {
+        Compiled{functions:functions, code:code, names:names.to_vec(),
+                 root:format!("{:?}", root)}
+    }
+
+    /// Evaluate the programme for a row of data
+    pub fn evaluate(&self, row:&[f64], policy:Policy) -> Result<f64, EvalError> {
+        let mut stack:Vec<f64> = Vec::new();
+        self.run(&self.code[..], row, &[], policy, &mut stack)?;
+        let ret = stack.pop().unwrap();
+        if ret.is_finite() {
+            Ok(ret)
+        }else{
+            Err(EvalError::NonFinite(self.root.clone()))
+        }
+    }
+
+    // Run `code` leaving its value on the top of `stack`
+    fn run(&self, code:&[Instruction], row:&[f64], args:&[f64],
+           policy:Policy, stack:&mut Vec<f64>) -> Result<(), EvalError> {
+        let mut pc = 0;
+        while pc < code.len() {
+            match code[pc] {
+                Instruction::Const(f) => {
+                    if policy == Policy::Checked && !f.is_finite() {
+                        return Err(EvalError::NonFinite(
+                            format!("{:?}", Operator::Terminal(TerminalType::Float(f)))));
+                    }
+                    stack.push(f);
+                },
+                Instruction::Input(i) => {
+                    let v = row[i];
+                    if policy == Policy::Checked && !v.is_finite() {
+                        let s = self.names[i].clone();
+                        return Err(EvalError::NonFinite(
+                            format!("{:?}", Operator::Terminal(TerminalType::Inputf64(s)))));
+                    }
+                    stack.push(v);
+                },
+                Instruction::Arg(a) => match args.get(a) {
+                    Some(v) => stack.push(*v),
+                    None => return Err(EvalError::MissingInput(format!("Arg {}", a))),
+                },
+                Instruction::Unary(ref o) => {
+                    let l = stack.pop().unwrap();
+                    stack.push(o.apply(l, 0.0, policy)?);
+                },
+                Instruction::Binary(ref o) => {
+                    let r = stack.pop().unwrap();
+                    let l = stack.pop().unwrap();
+                    stack.push(o.apply(l, r, policy)?);
+                },
+                Instruction::Call(k, n) => {
+                    // The ADF's code leaves its value on the stack
+                    // where its arguments were
+                    let at = stack.len() - n;
+                    let a:Vec<f64> = stack.split_off(at);
+                    self.run(&self.functions[k][..], row, &a[..], policy, stack)?;
+                },
+                Instruction::Branch(t) => {
+                    // A NaN condition is not positive
+                    let c = stack.pop().unwrap();
+                    if c <= 0.0 || c.is_nan() {
+                        pc = t;
+                        continue;
+                    }
+                },
+                Instruction::Jump(t) => {
+                    pc = t;
+                    continue;
+                },
+                Instruction::Fail(ref e) => return Err(e.clone()),
+            }
+            pc += 1;
+        }
+        Ok(())
+    }
+}
@@ -95,6 +95,7 @@ impl Config {
             _ => None,
         }
     }
+    #[allow(dead_code)]
     pub fn get_u32(&self, k:&str) -> Option<u32> {
         match self._get(k).parse::<u32>() {
             Ok(x) => Some(x),
@@ -7,10 +7,19 @@
 
 // Data starts from second line and continues to end of file.
 
+// A column name can have a unit in square brackets. E.g. `Length[mm]`.
+// See units.rs
+
 use File;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::f64;
 use std::io::BufReader;
 use std::io::BufRead;
-use super::rng;
+use std::io::Write;
+use rng::Stream;
+use units::Unit;
+use units::UnitType;
 
 #[derive(Debug, Clone)]
 /// Hold data for training or testing.  Data is columnated where each
@@ -29,30 +38,117 @@ pub struct Data {
     // last column)
     // pub input_names:Vec<&'a str>,
     pub input_names:Vec<String>,
+
+    /// The unit of each column, in the same order as `names`.  `None`
+    /// if the column has no unit
+    pub units:Vec<UnitType>,
     
     /// Each row of inputs.  
     pub data:Vec<Vec<f64>>,
 
+    /// The same data a column at a time.  Column major view of
+    /// `data` for evaluating a tree over many rows at once.  Set by
+    /// `columnate`
+    pub columns:Vec<Vec<f64>>,
+
     /// Indexes into rows for training data
     pub training_i:Vec<usize>,
 
+    /// Indexes into rows for validation data.  Used to choose the
+    /// best individual, not to evolve them
+    pub validation_i:Vec<usize>,
+
     /// Indexes into rows for testing data
     pub testing_i:Vec<usize>,
 
+    /// When classifying the distinct values of the last column, in
+    /// order.  Each is a class.  Empty otherwise.  Set by
+    /// `set_classes`
+    pub classes:Vec<f64>,
+}
+
+/// The parts the rows are divided into
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Partition {
+    Training,
+    Validation,
+    Testing,
+}
+
+impl Partition {
+    /// `None` if `s` is not the name of a partition
+    pub fn parse(s:&str) -> Option<Partition> {
+        match s {
+            "training" => Some(Partition::Training),
+            "validation" => Some(Partition::Validation),
+            "testing" => Some(Partition::Testing),
+            _ => None,
+        }
+    }
+    pub fn to_str(&self) -> &'static str {
+        match *self {
+            Partition::Training => "training",
+            Partition::Validation => "validation",
+            Partition::Testing => "testing",
+        }
+    }
+}
+
+/// How the rows are grouped so each partition gets its share of every
+/// group
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stratify {
+    // One group
+    None,
+
+    // Rows are sorted by the objective and cut into this many groups
+    // of near the same size
+    Objective(usize),
+
+    // A group for each distinct value of the objective
+    Class,
+}
+
+impl Stratify {
+    /// E.g: `none`, `class`, `objective` or `objective:10`.  Four
+    /// groups if the number is left out
+    pub fn new_from_str(s:&str) -> Stratify {
+        let v:Vec<&str> = s.split(':').collect();
+        match (v[0], v.len()) {
+            ("none", 1) => Stratify::None,
+            ("class", 1) => Stratify::Class,
+            ("objective", 1) => Stratify::Objective(4),
+            ("objective", 2) => Stratify::Objective(
+                v[1].parse::<usize>().expect("Stratify: number of groups")),
+            _ => panic!("Unknown stratification: {}", s),
+        }
+    }
 }
 
 impl Data {
-    pub fn new(data_file:&str, training_percent:usize) -> Data {
+    /// Read `data_file` and partition it with random numbers from
+    /// `rng`.  The rows not for training or validation are for
+    /// testing
+    pub fn new(data_file:&str, training_percent:usize, validation_percent:usize,
+               stratify:Stratify, rng:&mut Stream) -> Data {
+        if training_percent + validation_percent > 100 {
+            panic!("Training ({}%) and validation ({}%) are more than all the data",
+                   training_percent, validation_percent);
+        }
         let mut ret = Data{
             names:Vec::<String>::new(),
             //input_names:Vec::<&'a str>::new(),
             input_names:Vec::<String>::new(),
+            units:Vec::<UnitType>::new(),
             data:Vec::<Vec<f64>>::new(),
+            columns:Vec::<Vec<f64>>::new(),
             testing_i:Vec::<usize>::new(),
+            validation_i:Vec::<usize>::new(),
             training_i:Vec::<usize>::new(),
+            classes:Vec::<f64>::new(),
         };
         
-        ret.read_data(data_file, training_percent).
+        ret.read_data(data_file, training_percent, validation_percent, stratify, rng).
             expect(format!("Could not read: {}", data_file).as_str());
         ret
     }
@@ -61,34 +157,242 @@ impl Data {
         self.names = Vec::<String>::new();
         // self.input_names = Vec::<&'a str>::new();
         self.input_names = Vec::<String>::new();
+        self.units = Vec::<UnitType>::new();
         self.data = Vec::<Vec<f64>>::new();
+        self.columns = Vec::<Vec<f64>>::new();
         self.testing_i = Vec::<usize>::new();
+        self.validation_i = Vec::<usize>::new();
         self.training_i = Vec::<usize>::new();
+        self.classes = Vec::<f64>::new();
     }        
 
+    /// Classify the rows by the value of the last column
+    pub fn set_classes(&mut self) {
+        let mut classes:Vec<f64> = self.data.iter().map(|r| *r.last().unwrap()).collect();
+        classes.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        classes.dedup();
+        self.classes = classes;
+    }
+
+    /// The name of a class.  E.g: `2`
+    pub fn class_name(class:f64) -> String {
+        format!("{}", class)
+    }
+
+    /// The rows in a partition
+    pub fn rows(&self, partition:Partition) -> &Vec<usize> {
+        match partition {
+            Partition::Training => &self.training_i,
+            Partition::Validation => &self.validation_i,
+            Partition::Testing => &self.testing_i,
+        }
+    }
+
+    /// The rows shuffled with `rng` and dealt into `k` folds.  The
+    /// sizes of the folds differ by at most one
+    pub fn folds(&self, k:usize, rng:&mut Stream) -> Vec<Vec<usize>> {
+        let mut rows:Vec<usize> = (0..self.data.len()).collect();
+        rng.shuffle(&mut rows[..]);
+        let mut ret = vec![Vec::new(); k];
+        for (i, r) in rows.into_iter().enumerate() {
+            ret[i % k].push(r);
+        }
+        for f in ret.iter_mut() {
+            f.sort();
+        }
+        ret
+    }
+
+    /// Test on fold `i` and train on all the others.  There is no
+    /// validation data
+    pub fn set_fold(&mut self, folds:&[Vec<usize>], i:usize) {
+        self.testing_i = folds[i].clone();
+        self.validation_i = Vec::new();
+        self.training_i = folds.iter().enumerate().filter(|&(j, _)| j != i).
+            flat_map(|(_, f)| f.iter().cloned()).collect();
+        self.training_i.sort();
+    }
+
+    #[allow(dead_code)]
     pub fn ith_row(&self, i:usize) -> &Vec<f64> {
         &self.data[i]
     }
 
+    /// The smallest and largest value of each input
+    pub fn ranges(&self) -> HashMap<String, (f64, f64)> {
+        let mut ret = HashMap::new();
+        for j in 0..self.input_names.len() {
+            let r = self.data.iter().fold((f64::INFINITY, f64::NEG_INFINITY),
+                                          |r, x| (r.0.min(x[j]), r.1.max(x[j])));
+            ret.insert(self.input_names[j].clone(), r);
+        }
+        ret
+    }
+
+    /// Build the column major view of the data from the rows
+    pub fn columnate(&mut self) {
+        self.columns = (0..self.names.len()).map(|j| {
+            self.data.iter().map(|r| r[j]).collect()
+        }).collect();
+    }
+
     fn add_data_row(&mut self, row:Vec<f64>){
         self.data.push(row);
     }
     
-    fn partition(&mut self, training_percent:usize){
-        // Partition the data into training and testing sets
-        for i in 0..self.data.len() {
-            let z = rng::gen_range(0, 100);
-            if z < training_percent {
-                self.training_i.push(i);
+    // Partition the data into training, validation and testing sets
+    // of exactly the sizes asked for.  Each group of rows is
+    // shuffled and the groups laid end to end.  The rows of a
+    // partition are taken evenly spaced along them, so every group
+    // is split in the same proportions
+    fn partition(&mut self, training_percent:usize, validation_percent:usize,
+                 stratify:Stratify, rng:&mut Stream){
+        let n = self.data.len();
+        let mut order:Vec<usize> = Vec::new();
+        for mut s in self.strata(stratify) {
+            rng.shuffle(&mut s[..]);
+            order.extend(s);
+        }
+        self.training_i = Data::take_spaced(&mut order, (n * training_percent + 50) / 100);
+        self.validation_i = Data::take_spaced(&mut order, (n * validation_percent + 50) / 100);
+        self.testing_i = order;
+        self.training_i.sort();
+        self.validation_i.sort();
+        self.testing_i.sort();
+    }
+
+    // The rows in groups for stratifying.  Rows are in order of their
+    // objective value
+    fn strata(&self, stratify:Stratify) -> Vec<Vec<usize>> {
+        let n = self.data.len();
+        let mut rows:Vec<usize> = (0..n).collect();
+        if stratify == Stratify::None || self.columns.is_empty() {
+            return vec![rows];
+        }
+        let ref y = self.columns[self.columns.len() - 1];
+        rows.sort_by(|a, b| y[*a].partial_cmp(&y[*b]).unwrap_or(Ordering::Equal));
+        let mut ret:Vec<Vec<usize>> = Vec::new();
+        for (p, r) in rows.iter().enumerate() {
+            let new_group = match stratify {
+                Stratify::Class => p == 0 || y[*r] != y[rows[p - 1]],
+                Stratify::Objective(k) => p == 0 || p * k / n != (p - 1) * k / n,
+                Stratify::None => p == 0,
+            };
+            if new_group {
+                ret.push(Vec::new());
+            }
+            ret.last_mut().unwrap().push(*r);
+        }
+        ret
+    }
+
+    // Take `k` of `rows` evenly spaced along it.  The rest are left
+    // in `rows`
+    fn take_spaced(rows:&mut Vec<usize>, k:usize) -> Vec<usize> {
+        let n = rows.len();
+        let mut taken = Vec::new();
+        let mut rest = Vec::new();
+        for (j, r) in rows.iter().enumerate() {
+            if (j + 1) * k / n > j * k / n {
+                taken.push(*r);
             }else{
-                self.testing_i.push(i);
+                rest.push(*r);
             }
         }
-    }        
+        *rows = rest;
+        taken
+    }
+
+    // Set the partition of each row
+    fn set_partition(&mut self, parts:&[Partition]) -> std::io::Result<()> {
+        if parts.len() != self.data.len() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Partition is for {} rows but there are {}",
+                        parts.len(), self.data.len())));
+        }
+        self.training_i = Vec::new();
+        self.validation_i = Vec::new();
+        self.testing_i = Vec::new();
+        for (i, p) in parts.iter().enumerate() {
+            match *p {
+                Partition::Training => self.training_i.push(i),
+                Partition::Validation => self.validation_i.push(i),
+                Partition::Testing => self.testing_i.push(i),
+            }
+        }
+        Ok(())
+    }
+
+    /// The partition each row is in
+    pub fn partitions(&self) -> Vec<Partition> {
+        let mut ret = vec![Partition::Testing; self.data.len()];
+        for i in self.training_i.iter() {
+            ret[*i] = Partition::Training;
+        }
+        for i in self.validation_i.iter() {
+            ret[*i] = Partition::Validation;
+        }
+        ret
+    }
+
+    /// Take the partition from the input column `name`, which is then
+    /// removed from the data.  In it 0 is training, 1 validation and
+    /// 2 testing
+    pub fn partition_from_column(&mut self, name:&str) {
+        let j = match self.input_names.iter().position(|x| x == name) {
+            Some(j) => j,
+            None => panic!("No input column: {}", name),
+        };
+        let parts:Vec<Partition> = self.data.iter_mut().map(|r| {
+            let x = r.remove(j);
+            if x == 0.0 {
+                Partition::Training
+            }else if x == 1.0 {
+                Partition::Validation
+            }else if x == 2.0 {
+                Partition::Testing
+            }else{
+                panic!("Column {}.  Partition must be 0, 1 or 2 not {}", name, x)
+            }
+        }).collect();
+        self.names.remove(j);
+        self.units.remove(j);
+        self.input_names.remove(j);
+        self.columnate();
+        self.set_partition(&parts[..]).expect("A partition for every row");
+    }
+
+    /// Read the partition from a file with a line for each row:
+    /// `training`, `validation` or `testing`
+    pub fn read_partition(&mut self, f_name:&str) -> std::io::Result<()> {
+        let buf_reader = BufReader::new(File::open(f_name)?);
+        let mut parts:Vec<Partition> = Vec::new();
+        for (i, line) in buf_reader.lines().enumerate() {
+            let line = line?;
+            match Partition::parse(line.trim()) {
+                Some(p) => parts.push(p),
+                None => return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("Line {}: Unknown partition: {}", i + 1, line))),
+            }
+        }
+        self.set_partition(&parts[..])
+    }
+
+    /// Write the partition so `read_partition` can restore it
+    pub fn write_partition(&self, f_name:&str) -> std::io::Result<()> {
+        let mut file = File::create(f_name)?;
+        for p in self.partitions() {
+            file.write_all(format!("{}\n", p.to_str()).as_bytes())?;
+        }
+        Ok(())
+    }
 
     /// Read in the data from a file
     fn read_data(&mut self, f_name:&str,
-                 training_percent:usize)  -> std::io::Result<()>{
+                 training_percent:usize, validation_percent:usize,
+                 stratify:Stratify, rng:&mut Stream)  -> std::io::Result<()>{
 
         // Must be in file f_name.  First row is a header with names.
         self.reset();
@@ -104,9 +408,18 @@ impl Data {
 
         for i in 0..h_names.len() {
 
-            // Get the name of input/class
-            let s = h_names.iter().nth(i).unwrap();
-            self.names.push(s.to_string());
+            // Get the name of input/class and its unit if it has one
+            let s = h_names.iter().nth(i).unwrap().trim_end();
+            match (s.find('['), s.find(']')) {
+                (Some(a), Some(b)) if a < b => {
+                    self.names.push(format!("{}{}", &s[..a], &s[b+1..]));
+                    self.units.push(Some(Unit::new_from_str(&s[a+1..b])));
+                },
+                _ => {
+                    self.names.push(s.to_string());
+                    self.units.push(None);
+                },
+            };
         }
         for i in 0..self.names.len() - 1 {
             self.input_names.push(self.names[i].clone());
@@ -126,7 +439,8 @@ impl Data {
                 },
             };
         }
-        self.partition(training_percent);
+        self.columnate();
+        self.partition(training_percent, validation_percent, stratify, rng);
         Ok(())
     }
 }
@@ -0,0 +1,190 @@
+//! How well the estimates of a tree fit the true values.  The metric
+//! is chosen with the `fitness` configuration key.  Selection uses a
+//! quality made from the metric that is always in (0, 1] with higher
+//! better, whichever way the metric goes.
+//!
+//! For the classification metrics (`hinge` and `logloss`) a true
+//! value above zero is the class and any other is not
+
+use std::f64;
+
+pub trait FitnessFunction {
+    /// The metric over all the cases scored
+    fn metric(&self, truth:&[f64], estimate:&[f64]) -> f64;
+
+    /// Metrics where higher is better are at most one.  The others
+    /// are at least zero
+    fn higher_is_better(&self) -> bool;
+
+    /// The error for one case.  Used by lexicase selection
+    fn case_error(&self, truth:f64, estimate:f64) -> f64 {
+        (truth - estimate).abs()
+    }
+
+    /// The quality of a metric.  Increases as the metric gets better
+    fn quality(&self, metric:f64) -> f64 {
+        if self.higher_is_better() {
+            1.0 / (2.0 - metric)
+        }else{
+            1.0 / (1.0 + metric)
+        }
+    }
+}
+
+// The mean of `f` over the cases
+fn mean_of<F>(truth:&[f64], estimate:&[f64], f:F) -> f64
+    where F:Fn(f64, f64) -> f64 {
+    truth.iter().zip(estimate).map(|(t, e)| f(*t, *e)).sum::<f64>() /
+        truth.len() as f64
+}
+
+fn mean(x:&[f64]) -> f64 {
+    x.iter().sum::<f64>() / x.len() as f64
+}
+
+// -1 or 1 for classification
+fn sign(t:f64) -> f64 {
+    if t > 0.0 {1.0} else {-1.0}
+}
+
+/// Root mean square error
+pub struct Rmse;
+impl FitnessFunction for Rmse {
+    fn metric(&self, truth:&[f64], estimate:&[f64]) -> f64 {
+        mean_of(truth, estimate, |t, e| (t - e).powi(2)).sqrt()
+    }
+    fn higher_is_better(&self) -> bool {
+        false
+    }
+}
+
+/// Mean absolute error
+pub struct Mae;
+impl FitnessFunction for Mae {
+    fn metric(&self, truth:&[f64], estimate:&[f64]) -> f64 {
+        mean_of(truth, estimate, |t, e| (t - e).abs())
+    }
+    fn higher_is_better(&self) -> bool {
+        false
+    }
+}
+
+/// The coefficient of determination.  One less the ratio of the
+/// residual sum of squares to the total sum of squares
+pub struct RSquared;
+impl FitnessFunction for RSquared {
+    fn metric(&self, truth:&[f64], estimate:&[f64]) -> f64 {
+        let m = mean(truth);
+        let ss_res = truth.iter().zip(estimate).map(|(t, e)| (t - e).powi(2)).sum::<f64>();
+        let ss_tot = truth.iter().map(|t| (t - m).powi(2)).sum::<f64>();
+        1.0 - ss_res / ss_tot
+    }
+    fn higher_is_better(&self) -> bool {
+        true
+    }
+}
+
+/// Mean hinge loss.  An estimate of 1 for the class and -1 for not
+/// is perfect, as is anything further out
+pub struct Hinge;
+impl FitnessFunction for Hinge {
+    fn metric(&self, truth:&[f64], estimate:&[f64]) -> f64 {
+        mean_of(truth, estimate, |t, e| self.case_error(t, e))
+    }
+    fn higher_is_better(&self) -> bool {
+        false
+    }
+    fn case_error(&self, truth:f64, estimate:f64) -> f64 {
+        let l = 1.0 - sign(truth) * estimate;
+        if l < 0.0 {0.0} else {l}
+    }
+}
+
+/// Mean log loss.  The estimate is taken as the log odds of the case
+/// being of the class
+pub struct LogLoss;
+impl FitnessFunction for LogLoss {
+    fn metric(&self, truth:&[f64], estimate:&[f64]) -> f64 {
+        mean_of(truth, estimate, |t, e| self.case_error(t, e))
+    }
+    fn higher_is_better(&self) -> bool {
+        false
+    }
+    fn case_error(&self, truth:f64, estimate:f64) -> f64 {
+        // Keep the probability off 0 and 1 so the loss is finite
+        let p = (1.0 / (1.0 + (-estimate).exp())).max(1e-15).min(1.0 - 1e-15);
+        if truth > 0.0 {
+            -p.ln()
+        }else{
+            -(1.0 - p).ln()
+        }
+    }
+}
+
+/// Pearson's correlation of the estimates with the true values
+pub struct Correlation;
+impl FitnessFunction for Correlation {
+    fn metric(&self, truth:&[f64], estimate:&[f64]) -> f64 {
+        let mt = mean(truth);
+        let me = mean(estimate);
+        let mut cov = 0.0;
+        let mut vt = 0.0;
+        let mut ve = 0.0;
+        for (t, e) in truth.iter().zip(estimate) {
+            cov += (t - mt) * (e - me);
+            vt += (t - mt).powi(2);
+            ve += (e - me).powi(2);
+        }
+        cov / (vt * ve).sqrt()
+    }
+    fn higher_is_better(&self) -> bool {
+        true
+    }
+}
+
+/// The built in fitness functions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fitness {
+    Rmse,
+    Mae,
+    RSquared,
+    Hinge,
+    LogLoss,
+    Correlation,
+}
+
+impl Fitness {
+    pub fn new_from_str(s:&str) -> Fitness {
+        match s {
+            "rmse" => Fitness::Rmse,
+            "mae" => Fitness::Mae,
+            "r2" => Fitness::RSquared,
+            "hinge" => Fitness::Hinge,
+            "logloss" => Fitness::LogLoss,
+            "correlation" => Fitness::Correlation,
+            _ => panic!("Unknown fitness function: {}", s),
+        }
+    }
+
+    pub fn to_str(&self) -> &'static str {
+        match *self {
+            Fitness::Rmse => "rmse",
+            Fitness::Mae => "mae",
+            Fitness::RSquared => "r2",
+            Fitness::Hinge => "hinge",
+            Fitness::LogLoss => "logloss",
+            Fitness::Correlation => "correlation",
+        }
+    }
+
+    pub fn function(&self) -> &'static dyn FitnessFunction {
+        match *self {
+            Fitness::Rmse => &Rmse,
+            Fitness::Mae => &Mae,
+            Fitness::RSquared => &RSquared,
+            Fitness::Hinge => &Hinge,
+            Fitness::LogLoss => &LogLoss,
+            Fitness::Correlation => &Correlation,
+        }
+    }
+}
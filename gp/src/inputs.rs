@@ -11,6 +11,7 @@ impl Inputs {
             dataf:HashMap::new(),
         }
     }
+    #[allow(dead_code)]
     pub fn  insert(&mut self, k:&str, v:f64) {
         self.dataf.insert(k.to_string(), v);
     }
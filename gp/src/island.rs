@@ -0,0 +1,46 @@
+//! The island model.  The population is split into islands that
+//! evolve apart.  Every few generations the best individuals of each
+//! island migrate to others.  Which islands they go to is set by the
+//! `migration_topology` configuration key
+
+use rng::Stream;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Topology {
+    // Each island sends migrants to the next
+    Ring,
+
+    // Each island sends migrants to every other
+    Full,
+
+    // Each island sends migrants to another chosen at random each
+    // time
+    Random,
+}
+
+impl Topology {
+    pub fn new_from_str(s:&str) -> Topology {
+        match s {
+            "ring" => Topology::Ring,
+            "full" => Topology::Full,
+            "random" => Topology::Random,
+            _ => panic!("Unknown migration topology: {}", s),
+        }
+    }
+
+    /// The islands that island `i` of `n` sends migrants to
+    pub fn destinations(&self, i:usize, n:usize, rng:&mut Stream) -> Vec<usize> {
+        if n < 2 {
+            return Vec::new();
+        }
+        match *self {
+            Topology::Ring => vec![(i + 1) % n],
+            Topology::Full => (0..n).filter(|j| *j != i).collect(),
+            Topology::Random => {
+                // Any island but this one
+                let j = rng.gen_range(0, n - 1);
+                vec![if j < i {j} else {j + 1}]
+            },
+        }
+    }
+}
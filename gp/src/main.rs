@@ -1,15 +1,22 @@
-#[macro_use] extern crate lazy_static;
 extern crate fs2;
 extern crate rand;
 extern crate statistical;
+mod cache;
+mod compiled;
 mod config;
 mod controller;
 mod data;
+mod fitness;
 mod inputs;
+mod island;
 mod node;
+mod pareto;
 mod population;
 mod rng;
 mod score;
+mod selection;
+mod tune;
+mod units;
 use config::Config;
 use data::Data;
 use node::NodeBox;
@@ -25,24 +32,72 @@ use std::time::SystemTime;
 #[cfg(test)]
 mod tests {
     use super::*;
+    use data::Partition;
+    use fitness::Fitness;
+    use data::Stratify;
     use inputs::Inputs;
     use node::Node;
+    use node::Policy;
+    use cache::Cache;
+    use node::EvalError;
+    use score::ScoreError;
+    use node::FunctionSet;
+    use node::Primitives;
+    use std::collections::HashMap;
+    use units::Unit;
+    use rng::Stream;
     #[test]
     /// Test the partitioning of data
     fn test_data_partition() {
         let config = Config::new("TestConfig");
         let data_file = config.get_string("data_file").unwrap();
         
+        let mut rng = Stream::new(&[1], "test");
         {
             // Load the data with zero training
-            let d_all:Data = Data::new(data_file.as_str(), 0);
+            let d_all:Data = Data::new(data_file.as_str(), 0, 0, Stratify::None, &mut rng);
             assert_eq!(d_all.training_i.len(), 0);
         }
         {
             // Load the data with zero testing
-            let d_all:Data = Data::new(data_file.as_str(), 100);
+            let d_all:Data = Data::new(data_file.as_str(), 100, 0, Stratify::None, &mut rng);
             assert_eq!(d_all.testing_i.len(), 0);
         }
+        {
+            // The same seed partitions the same way
+            let d1 = Data::new(data_file.as_str(), 50, 0, Stratify::None, &mut Stream::new(&[2, 3, 8], "partition"));
+            let d2 = Data::new(data_file.as_str(), 50, 0, Stratify::None, &mut Stream::new(&[2, 3, 8], "partition"));
+            assert_eq!(d1.training_i, d2.training_i);
+        }
+        {
+            // Three way partition covers every row once
+            let d = Data::new(data_file.as_str(), 60, 20, Stratify::None, &mut rng);
+            let mut all:Vec<usize> = d.training_i.iter().chain(d.validation_i.iter()).
+                chain(d.testing_i.iter()).cloned().collect();
+            all.sort();
+            assert_eq!(all, (0..d.data.len()).collect::<Vec<usize>>());
+        }
+        {
+            // Folds are near the same size and each is tested once
+            let mut d = Data::new(data_file.as_str(), 100, 0, Stratify::None, &mut rng);
+            let folds = d.folds(3, &mut rng);
+            let n = d.data.len();
+            for i in 0..3 {
+                assert!(folds[i].len() == n / 3 || folds[i].len() == n / 3 + 1);
+                d.set_fold(&folds[..], i);
+                assert_eq!(d.testing_i, folds[i]);
+                assert_eq!(d.training_i.len() + d.testing_i.len(), n);
+            }
+        }
+
+        // Streams with different seeds or names are different
+        let mut a = Stream::new(&[2, 3, 8], "partition");
+        let mut b = Stream::new(&[2, 3, 8], "variation");
+        let mut c = Stream::new(&[2, 3, 9], "partition");
+        let x:Vec<u32> = (0..4).map(|_| a.random()).collect();
+        let y:Vec<u32> = (0..4).map(|_| b.random()).collect();
+        let z:Vec<u32> = (0..4).map(|_| c.random()).collect();
+        assert!(x != y && x != z);
     }
     #[test]
     /// Test the evaluation of a node
@@ -50,55 +105,55 @@ mod tests {
         let mut inputs = Inputs::new();
         let s = "Multiply Float 0.06 Negate Diameter";
         inputs.insert("Diameter", 10.0);
-        let t = Node::new_from_str(s).evaluate(&inputs).unwrap();
+        let t = Node::new_from_str(s).evaluate(&inputs, Policy::Strict).unwrap();
         assert_eq!(t, -0.60);
 
         let mut inputs = Inputs::new();
         let s = "Invert Float 0.1";
         inputs.insert("Diameter", 10.0);
-        let t = Node::new_from_str(s).evaluate(&inputs).unwrap();
+        let t = Node::new_from_str(s).evaluate(&inputs, Policy::Strict).unwrap();
         assert_eq!(t, 10.0);
 
         let inputs = Inputs::new();
         let s = "Add Float 2000.0 Invert Float 0.1";
-        let t = Node::new_from_str(s).evaluate(&inputs).unwrap();
+        let t = Node::new_from_str(s).evaluate(&inputs, Policy::Strict).unwrap();
         assert_eq!(t, 2010.0);
 
         let mut inputs = Inputs::new();
         let s = "Multiply Height Add Float 10.0 Invert Float 0.1";
         inputs.insert("Height", 10.0);
-        let t = Node::new_from_str(s).evaluate(&inputs).unwrap();
+        let t = Node::new_from_str(s).evaluate(&inputs, Policy::Strict).unwrap();
         assert_eq!(t, 200.0);
 
         let s = "If Lt x Float 0.0 Float -1.0 Float 1.0";
         let n = Node::new_from_str(s);
         let mut inputs = Inputs::new();
         inputs.insert("x", 1.0);
-        let t = n.evaluate(&inputs).unwrap();
+        let t = n.evaluate(&inputs, Policy::Strict).unwrap();
         assert_eq!(t, 1.0);
 
         let mut inputs = Inputs::new();
         inputs.insert("x", 0.0);
-        let t = n.evaluate(&inputs).unwrap();
+        let t = n.evaluate(&inputs, Policy::Strict).unwrap();
         assert_eq!(t, 1.0);
 
         let mut inputs = Inputs::new();
         inputs.insert("x", -0.01);
-        let t = n.evaluate(&inputs).unwrap();
+        let t = n.evaluate(&inputs, Policy::Strict).unwrap();
         assert_eq!(t, -1.0);        
 
         let s = "Gt Log If x x x x";
         let n = Node::new_from_str(s);
         let mut inputs = Inputs::new();
         inputs.insert("x", 1.0);
-        let t = n.evaluate(&inputs).unwrap();
+        let t = n.evaluate(&inputs, Policy::Strict).unwrap();
         assert_eq!(t, -1.0);        
 
         let s = "Lt Log If x x x x";
         let n = Node::new_from_str(s);
         let mut inputs = Inputs::new();
         inputs.insert("x", 1.0);
-        let t = n.evaluate(&inputs).unwrap();
+        let t = n.evaluate(&inputs, Policy::Strict).unwrap();
         assert_eq!(t, 1.0);        
 
         let s = "If Lt x y x y";
@@ -106,7 +161,7 @@ mod tests {
         let mut inputs = Inputs::new();
         inputs.insert("x", 1.2);
         inputs.insert("y", 1.1);
-        let t = n.evaluate(&inputs).unwrap();
+        let t = n.evaluate(&inputs, Policy::Strict).unwrap();
         assert_eq!(t, 1.1);        
 
         let s = "If Lt x y x y";
@@ -114,7 +169,7 @@ mod tests {
         let mut inputs = Inputs::new();
         inputs.insert("x", -9.0);
         inputs.insert("y", 1.0);
-        let t = n.evaluate(&inputs).unwrap();
+        let t = n.evaluate(&inputs, Policy::Strict).unwrap();
         assert_eq!(t, -9.0);        
 
         let s = "Add Gt Remainder x y Float -0.1 Lt Remainder x y Float 0.1";
@@ -122,22 +177,255 @@ mod tests {
         let mut inputs = Inputs::new();
         inputs.insert("x", 92.0);
         inputs.insert("y", 3.0);
-        let t = n.evaluate(&inputs).unwrap();
+        let t = n.evaluate(&inputs, Policy::Strict).unwrap();
         assert_eq!(t, 0.0);        
     }
     #[test]
+    /// Test evaluating a programme with automatically defined functions
+    fn test_adf_eval(){
+        let s = "Defun 2 Add Arg 0 Multiply Arg 1 Arg 1 Defun 1 Negate Arg 0 Adf 1 Adf 0 x Float 3";
+        let n = Node::new_from_str(s);
+        assert_eq!(n.to_string().trim(), s);
+
+        let mut inputs = Inputs::new();
+        inputs.insert("x", 1.0);
+        let t = n.evaluate(&inputs, Policy::Strict).unwrap();
+        assert_eq!(t, -10.0);
+
+        // The two `Defun` nodes are not crossover points
+        assert_eq!(n.points().len(), n.count_nodes() - 2);
+    }
+    #[test]
+    /// Test units stop inputs being combined in ways that make no sense
+    fn test_units(){
+        let mut rng = Stream::new(&[1], "test");
+        let mut units = HashMap::new();
+        units.insert("Length".to_string(), Unit::new_from_str("mm"));
+        units.insert("Weight".to_string(), Unit::new_from_str("g"));
+
+        assert!(Node::new_from_str("Add Length Weight").unit(&units).is_err());
+        assert!(Node::new_from_str("Gt Length Weight").unit(&units).is_err());
+        assert!(Node::new_from_str("Log Length").unit(&units).is_err());
+        assert!(Node::new_from_str("Add Length Multiply Float 2.0 Weight").
+                unit(&units).is_err());
+        assert!(Node::new_from_str("Add Weight Multiply Float 1 Length").
+                unit(&units).is_err());
+        assert_eq!(Node::new_from_str("Multiply Float 2.0 Divide Length Float 3.0").
+                   unit(&units).unwrap(), Some(Unit::new_from_str("mm")));
+        // A call has the unit of the ADF body.  Arguments are
+        // dimensionless
+        assert_eq!(Node::new_from_str("Defun 1 Multiply Length Arg 0 Multiply Weight Adf 0 Float 2.0").
+                   unit(&units).unwrap(), Some(Unit::new_from_str("mm*g")));
+        assert!(Node::new_from_str("Defun 1 Arg 0 Adf 0 Weight").unit(&units).is_err());
+        assert_eq!(Node::new_from_str("Multiply Weight Invert Multiply Length Length").
+                   unit(&units).unwrap(), Some(Unit::new_from_str("g*mm^-2")));
+
+        // Random trees make sense
+        let prims = Primitives{
+            names:vec!["Length".to_string(), "Weight".to_string(), "x".to_string()],
+            adfs:vec![2],
+            units:units,
+            objective:Some(Unit::new_from_str("g")),
+            functions:FunctionSet::new(),
+        };
+        for _ in 0..100 {
+            assert!(Node::new_program(&prims, &mut rng).type_check(&prims));
+        }
+        let t = Node::new_from_str("Add Weight Multiply Float 1 Length");
+        assert!(!t.type_check(&prims));
+    }
+    #[test]
+    /// Test only operators in the function set are used
+    fn test_function_set(){
+        let mut rng = Stream::new(&[1], "test");
+        let prims = Primitives{
+            names:vec!["x".to_string(), "y".to_string()],
+            adfs:vec![],
+            units:HashMap::new(),
+            objective:None,
+            functions:FunctionSet::new_from_str("Add:2 Multiply Gt:0 Float Input:4"),
+        };
+        for _ in 0..100 {
+            let s = Node::new_program(&prims, &mut rng).to_string();
+            for w in s.split_whitespace() {
+                match w {
+                    "Add"|"Multiply"|"Float"|"x"|"y" => (),
+                    w => assert!(w.parse::<f64>().is_ok(), "{} in {}", w, s),
+                };
+            }
+        }
+    }
+    #[test]
+    /// Test the operators that are not in the default function set
+    fn test_extended_operators(){
+        let mut rng = Stream::new(&[1], "test");
+        let mut inputs = Inputs::new();
+        inputs.insert("x", 9.0);
+        inputs.insert("y", -2.0);
+        let cases = vec![
+            ("Subtract x y", 11.0),
+            ("Divide x y", -4.5),
+            ("Sqrt x", 3.0),
+            ("Exp Float 0", 1.0),
+            ("Sin Float 0", 0.0),
+            ("Cos Float 0", 1.0),
+            ("Tanh Float 0", 0.0),
+            ("Abs y", 2.0),
+            ("Min x y", -2.0),
+            ("Max x y", 9.0),
+            ("Pow y Float 3", -8.0),
+        ];
+        for (s, v) in cases {
+            let n = Node::new_from_str(s);
+            assert_eq!(n.to_string().trim(), s);
+            assert_eq!(n.evaluate(&inputs, Policy::Strict).unwrap(), v, "{}", s);
+        }
+
+        let mut units = HashMap::new();
+        units.insert("Area".to_string(), Unit::new_from_str("mm^2"));
+        units.insert("Length".to_string(), Unit::new_from_str("mm"));
+        assert_eq!(Node::new_from_str("Subtract Sqrt Area Length").
+                   unit(&units).unwrap(), Some(Unit::new_from_str("mm")));
+        assert_eq!(Node::new_from_str("Divide Area Length").
+                   unit(&units).unwrap(), Some(Unit::new_from_str("mm")));
+        assert!(Node::new_from_str("Sqrt Length").unit(&units).is_err());
+        assert!(Node::new_from_str("Exp Length").unit(&units).is_err());
+        assert!(Node::new_from_str("Max Area Length").unit(&units).is_err());
+
+        // Random trees using them make sense
+        let prims = Primitives{
+            names:vec!["Area".to_string(), "Length".to_string()],
+            adfs:vec![],
+            units:units,
+            objective:Some(Unit::new_from_str("mm")),
+            functions:FunctionSet::new_from_str(
+                "Subtract Divide Sqrt Exp Sin Cos Tanh Abs Min Max Pow Float Input:4"),
+        };
+        for _ in 0..100 {
+            let n = Node::new_program(&prims, &mut rng);
+            assert!(n.type_check(&prims), "{}", n.to_string());
+            let s = n.to_string();
+            assert_eq!(Node::new_from_str(s.as_str()).to_string(), s);
+        }
+    }
+    #[test]
+    /// Test exact, stratified and given partitions
+    fn test_data_stratify() {
+        // Twenty rows of class 0 and eighty of class 1.  The column
+        // `Set` puts the first ten in validation
+        let f_name = "/tmp/test_data_stratify";
+        let mut f = File::create(f_name).unwrap();
+        f.write_all("x,Set,y\n".as_bytes()).unwrap();
+        for i in 0..100 {
+            let line = format!("{},{},{}\n", i, if i < 10 {1} else {0},
+                               if i < 20 {0} else {1});
+            f.write_all(line.as_bytes()).unwrap();
+        }
+        let mut rng = Stream::new(&[4], "test");
+        let mut d = Data::new(f_name, 70, 10, Stratify::Class, &mut rng);
+        assert_eq!((d.training_i.len(), d.validation_i.len(), d.testing_i.len()),
+                   (70, 10, 20));
+        assert_eq!(d.training_i.iter().filter(|i| **i < 20).count(), 14);
+        assert_eq!(d.testing_i.iter().filter(|i| **i < 20).count(), 4);
+
+        // Saved and read back
+        let parts = d.partitions();
+        d.write_partition("/tmp/test_data_stratify.partition").unwrap();
+        let mut e = Data::new(f_name, 50, 0, Stratify::Objective(3), &mut rng);
+        assert_eq!(e.training_i.len(), 50);
+        e.read_partition("/tmp/test_data_stratify.partition").unwrap();
+        assert_eq!(e.partitions(), parts);
+
+        // A partition for too few rows is an error, and changes nothing
+        let short = "/tmp/test_data_stratify_short.partition";
+        File::create(short).unwrap().write_all(b"training\ntesting\n").unwrap();
+        match e.read_partition(short) {
+            Err(x) => assert_eq!(x.kind(), std::io::ErrorKind::InvalidData),
+            Ok(_) => panic!("Read a partition for two rows"),
+        }
+        assert_eq!(e.partitions(), parts);
+
+        // So is a line that is not a partition
+        let bad = "/tmp/test_data_stratify_bad.partition";
+        let mut lines:String = parts.iter().map(|p| format!("{}\n", p.to_str())).collect();
+        lines.push_str("\n");
+        File::create(bad).unwrap().write_all(lines.as_bytes()).unwrap();
+        match e.read_partition(bad) {
+            Err(x) => assert_eq!(x.kind(), std::io::ErrorKind::InvalidData),
+            Ok(_) => panic!("Read a partition with a blank line"),
+        }
+        assert_eq!(e.partitions(), parts);
+
+        e.partition_from_column("Set");
+        assert_eq!(e.input_names, vec!["x".to_string()]);
+        assert_eq!(e.validation_i, (0..10).collect::<Vec<usize>>());
+        assert_eq!(e.training_i.len(), 90);
+        assert_eq!(e.columns.len(), 2);
+    }
+    #[test]
+    /// Test the fitness functions and the scores made from them
+    fn test_fitness() {
+        let t = [1.0, -1.0, 1.0, -1.0];
+        let e = [1.0, -1.0, 0.0, 1.0];
+        assert_eq!(Fitness::Rmse.function().metric(&t, &e), (5.0f64 / 4.0).sqrt());
+        assert_eq!(Fitness::Mae.function().metric(&t, &e), 0.75);
+        assert_eq!(Fitness::RSquared.function().metric(&t, &e), 1.0 - 5.0 / 4.0);
+        assert_eq!(Fitness::Hinge.function().metric(&t, &e), (0.0 + 0.0 + 1.0 + 2.0) / 4.0);
+        assert_eq!(Fitness::Correlation.function().metric(&t, &t), 1.0);
+        let l = Fitness::LogLoss.function();
+        assert!(l.metric(&t, &[9.0, -9.0, 9.0, -9.0]) < l.metric(&t, &e));
+
+        // Quality goes the same way as the metric whichever way is better
+        for f in [Fitness::Rmse, Fitness::RSquared, Fitness::Correlation].iter() {
+            let f = f.function();
+            let (good, bad) = if f.higher_is_better() {(0.9, 0.1)} else {(0.1, 0.9)};
+            assert!(f.quality(good) > f.quality(bad));
+            assert!(f.quality(good) <= 1.0 && f.quality(bad) > 0.0);
+        }
+
+        // The root mean square error is over the number of cases
+        let mut d = Data {
+            names:vec!["x".to_string(), "y".to_string()],
+            input_names:vec!["x".to_string()],
+            units:vec![None, None],
+            data:vec![vec![0.0, 1.0], vec![0.0, 1.0], vec![0.0, 1.0], vec![0.0, 1.0]],
+            columns:vec![],
+            training_i:vec![0, 1, 2, 3],
+            validation_i:vec![],
+            classes:vec![],
+            testing_i:vec![],
+        };
+        d.columnate();
+        let n = Box::new(Node::new_from_str("Float 3"));
+        let s = score_individual(&n, &d, Partition::Training, Policy::Strict,
+                                 Fitness::Rmse).unwrap();
+        assert_eq!((s.metric, s.error, s.quality), (2.0, 2.0, 1.0 / 3.0));
+        let s = score_individual(&n, &d, Partition::Training, Policy::Strict,
+                                 Fitness::Hinge).unwrap();
+        assert_eq!(s.errors[0], 0.0);
+        let s = score_individual(&n, &d, Partition::Training, Policy::Strict,
+                                 Fitness::Correlation);
+        assert!(s.is_err());
+    }
+    #[test]
     fn test_evaluation_remainder(){
-        let d = Data {
+        let mut d = Data {
             names:vec!["Q".to_string(), "Obj".to_string()],
             input_names:vec!["Q".to_string()],
+            units:vec![None, None],
             data:vec![vec![8116.0,1.0],vec![9122.0,2.0], vec![4407.0,0.0]],
+            columns:vec![],
             training_i:vec![0,1],
+            validation_i:vec![],
+            classes:vec![],
             testing_i:vec![2],
         };
+        d.columnate();
         {
             let s = "Remainder Q Float 3.0";
             let _n = Box::new(Node::new_from_str(s));
-            match score_individual(&_n, &d, false) {
+            match score_individual(&_n, &d, Partition::Training, Policy::Strict,
+                                   Fitness::Rmse) {
                 Ok(ss) => assert_eq!(ss.quality(), 1.0),
                 Err(e) => panic!("{:?}", e),
             };
@@ -146,7 +434,8 @@ mod tests {
         {
             let s = "Remainder Q Float 3.1";
             let _n = Box::new(Node::new_from_str(s));
-            match score_individual(&_n, &d, false) {
+            match score_individual(&_n, &d, Partition::Training, Policy::Strict,
+                                   Fitness::Rmse) {
                 Ok(ss) => assert_ne!(ss.quality(), 0.0),
                 Err(e) => panic!("{:?}", e),
             };
@@ -154,12 +443,261 @@ mod tests {
     }
     #[test]
     /// Test rationalising a Tree
-    // fn test_rationalise(){
-    //     let n1 = Node::new_from_str("Float 1.0");
-    //     let n2 = n1.rationalise();
-    //     assert_eq!(n1.to_string(), n2.to_string());
-        
-    // }
+    fn test_rationalise(){
+        let n1 = Node::new_from_str("Float 1.0");
+        let n2 = n1.rationalise(Policy::Strict);
+        assert_eq!(n1.to_string(), n2.to_string());
+
+        let cases = vec![
+            ("Add Float 2.0 Multiply Float 3.0 Float 4.0", "Float 14"),
+            ("Lt Log If x x x x", "Lt Log x x"),
+            ("If Gt Float 1.0 Float 2.0 x y", "y"),
+            ("Negate Negate Add x Float 0.0", "x"),
+            ("Invert Invert Multiply Float 1.0 x", "x"),
+            ("Add x Negate Log Float -1.0", "Add x Negate Log Float -1"),
+            ("Defun 1 Multiply Arg 0 Float 1.0 Adf 0 Negate Negate x",
+             "Defun 1 Arg 0 Adf 0 x"),
+        ];
+        for (s, r) in cases {
+            let n = Node::new_from_str(s).rationalise(Policy::Strict);
+            assert_eq!(n.to_string().trim(), r);
+        }
+
+        // The value is unchanged
+        let s = "If Lt x Float 0.5 Add Multiply Float 2.0 x Float 0.0 Remainder x Add Float 1.0 Float 2.0";
+        let n = Node::new_from_str(s);
+        let r = n.rationalise(Policy::Strict);
+        assert!(r.count_nodes() < n.count_nodes());
+        for x in vec![-1.0, 0.0, 0.25, 0.5, 7.0] {
+            let mut inputs = Inputs::new();
+            inputs.insert("x", x);
+            assert_eq!(n.evaluate(&inputs, Policy::Strict), r.evaluate(&inputs, Policy::Strict));
+        }
+
+        // `Invert Invert x` is only `x` where they agree at zero
+        let n = Node::new_from_str("Invert Invert x");
+        let mut inputs = Inputs::new();
+        inputs.insert("x", 0.0);
+        for p in vec![Policy::Strict, Policy::Protected, Policy::Interval, Policy::Checked] {
+            let r = n.rationalise(p);
+            assert_eq!(n.evaluate(&inputs, p), r.evaluate(&inputs, p), "{:?}", p);
+        }
+    }
+
+    #[test]
+    fn test_policy(){
+        let mut inputs = Inputs::new();
+        inputs.insert("x", 0.0);
+        let n = Node::new_from_str("Invert x");
+        assert!(n.evaluate(&inputs, Policy::Strict).is_err());
+        assert_eq!(n.evaluate(&inputs, Policy::Protected), Ok(1.0));
+        let n = Node::new_from_str("Gt Invert x Float 1.0");
+        assert_eq!(n.evaluate(&inputs, Policy::Strict), Ok(1.0));
+        assert!(n.evaluate(&inputs, Policy::Checked).is_err());
+        let n = Node::new_from_str("Log Negate Float 1.0");
+        assert_eq!(n.evaluate(&inputs, Policy::Protected), Ok(0.0));
+
+        // Protected trees must not lose their protection when simplified
+        let n = Node::new_from_str("Invert Invert x");
+        let r = n.rationalise(Policy::Protected);
+        assert_eq!(n.evaluate(&inputs, Policy::Protected),
+                   r.evaluate(&inputs, Policy::Protected));
+
+        let mut ranges = HashMap::new();
+        ranges.insert("x".to_string(), (1.0, 2.0));
+        assert_eq!(Node::new_from_str("Add x Float 1.0").interval(&ranges),
+                   Some((2.0, 3.0)));
+        assert!(Node::new_from_str("Invert x").interval(&ranges).is_some());
+        ranges.insert("x".to_string(), (-1.0, 2.0));
+        assert!(Node::new_from_str("Invert x").interval(&ranges).is_none());
+        assert!(Node::new_from_str("Log x").interval(&ranges).is_none());
+    }
+
+    #[test]
+    fn test_eval_error(){
+        let mut inputs = Inputs::new();
+        inputs.insert("x", 0.0);
+        let cases = vec![
+            ("Add x y", EvalError::MissingInput("y".to_string())),
+            ("Add x Invert x", EvalError::Domain("Invert".to_string())),
+            ("Sqrt Negate Float 2.0", EvalError::Domain("Sqrt".to_string())),
+            ("Exp Float 1000.0", EvalError::NonFinite("Exp".to_string())),
+        ];
+        for (s, e) in cases {
+            let n = Node::new_from_str(s);
+            assert_eq!(n.evaluate(&inputs, Policy::Checked), Err(e), "{}", s);
+        }
+
+        // Under the strict policy only the value of the tree is checked
+        let n = Node::new_from_str("Add x Invert x");
+        assert_eq!(n.evaluate(&inputs, Policy::Strict),
+                   Err(EvalError::NonFinite("Add".to_string())));
+
+        // A tree that names a column not in the data fails to score
+        let mut d = Data{
+            names:vec!["x".to_string(), "y".to_string()],
+            units:vec![None, None],
+            input_names:vec!["x".to_string()],
+            data:vec![vec![1.0, 2.0]],
+            columns:vec![],
+            testing_i:vec![],
+            training_i:vec![0],
+            validation_i:vec![],
+            classes:vec![],
+        };
+        d.columnate();
+        let n = NodeBox::new(Node::new_from_str("Add x z"));
+        match score_individual(&n, &d, Partition::Training, Policy::Strict,
+                                   Fitness::Rmse) {
+            Err(ScoreError::FailedEvaluation(EvalError::MissingInput(s))) => assert_eq!(s, "z"),
+            r => panic!("{:?}", r),
+        }
+    }
+
+    #[test]
+    /// A compiled programme has the same value as the tree, and fails
+    /// the same way.  So does evaluating a column at a time
+    fn test_compile(){
+        let mut rng = Stream::new(&[1], "test");
+        let names = vec!["x".to_string(), "y".to_string()];
+        let rows = vec![vec![0.0, 1.0], vec![2.5, -3.0], vec![-0.5, 0.0]];
+        let columns = vec![vec![0.0, 2.5, -0.5], vec![1.0, -3.0, 0.0]];
+        let check = |n:&Node| {
+            let code = n.compile(&names[..]);
+            for p in vec![Policy::Strict, Policy::Protected, Policy::Checked] {
+                let mut values:Vec<f64> = Vec::new();
+                let mut failed = false;
+                for row in rows.iter() {
+                    let mut inputs = Inputs::new();
+                    inputs.insert("x", row[0]);
+                    inputs.insert("y", row[1]);
+                    let v = n.evaluate(&inputs, p);
+                    assert_eq!(code.evaluate(&row[..], p), v, "{}", n.to_string());
+                    match v {
+                        Ok(v) => values.push(v),
+                        Err(_) => failed = true,
+                    }
+                }
+
+                // Evaluating a column at a time fails if any row fails
+                let c = n.evaluate_columns(&names[..], &columns[..], &[0, 1, 2], p);
+                if failed {
+                    assert!(c.is_err(), "{}", n.to_string());
+                }else{
+                    assert_eq!(c, Ok(values), "{}", n.to_string());
+                }
+            }
+        };
+
+        // Only the leg of an `If` taken is evaluated, inputs not in
+        // the data only fail when they are used
+        let cases = vec![
+            "If Gt x Float 0 Invert x Log y",
+            "If Lt x Float 1 z Multiply x y",
+            "Defun 2 Add Arg 0 Invert Arg 1 Defun 1 Adf 0 Arg 0 Arg 0 Adf 1 Adf 0 x y",
+        ];
+        for s in cases {
+            check(&Node::new_from_str(s));
+        }
+        let prims = Primitives{
+            names:names.clone(),
+            adfs:vec![2, 1],
+            units:HashMap::new(),
+            objective:None,
+            functions:FunctionSet::new_from_str(
+                "Float Log Invert Negate Multiply Gt Lt Add Remainder If Adf \
+                 Subtract Divide Sqrt Exp Sin Min Pow Input:4"),
+        };
+        for _ in 0..200 {
+            check(&Node::new_program(&prims, &mut rng));
+        }
+    }
+
+    #[test]
+    /// Values of subtrees from the cache are the values evaluated
+    fn test_cache(){
+        let names = vec!["x".to_string(), "y".to_string()];
+        let columns = vec![vec![0.0, 2.5, -0.5], vec![1.0, -3.0, 0.0]];
+        let rows = vec![0, 1, 2];
+        let mut cache = Cache::new(1024 * 1024);
+        cache.prepare(&rows[..], Policy::Strict);
+        let s = "Add Multiply x y If Gt x Float 0 Multiply x y Negate y";
+        let n = Node::new_from_str(s);
+        let v = n.evaluate_columns(&names[..], &columns[..], &rows[..], Policy::Strict);
+        for _ in 0..2 {
+            assert_eq!(n.evaluate_columns_cached(&names[..], &columns[..], &rows[..],
+                                                 Policy::Strict, &mut cache), v);
+        }
+        // The second time the whole tree is found
+        assert!(cache.hits > 0);
+
+        // A subtree of the tree before
+        let n = Node::new_from_str("Multiply x y");
+        let v = n.evaluate_columns(&names[..], &columns[..], &rows[..], Policy::Strict);
+        let hits = cache.hits;
+        assert_eq!(n.evaluate_columns_cached(&names[..], &columns[..], &rows[..],
+                                             Policy::Strict, &mut cache), v);
+        assert_eq!(cache.hits, hits + 1);
+
+        // Trees that differ only in a constant have their own values
+        for s in vec!["Multiply x Float 2.0", "Multiply x Float 3.0"] {
+            let m = Node::new_from_str(s);
+            let v = m.evaluate_columns(&names[..], &columns[..], &rows[..], Policy::Strict);
+            assert_eq!(m.evaluate_columns_cached(&names[..], &columns[..], &rows[..],
+                                                 Policy::Strict, &mut cache), v);
+        }
+
+        // No room for anything
+        let mut cache = Cache::new(1);
+        cache.prepare(&rows[..], Policy::Strict);
+        for _ in 0..2 {
+            n.evaluate_columns_cached(&names[..], &columns[..], &rows[..],
+                                      Policy::Strict, &mut cache).unwrap();
+        }
+        assert_eq!(cache.hits, 0);
+    }
+
+    #[test]
+    /// The constants of a tree can be tuned
+    fn test_tune(){
+        let mut n = Node::new_from_str("Add Multiply x Float 2.0 If x Float 1.0 Float -3.5");
+        assert_eq!(n.constants(), vec![2.0, 1.0, -3.5]);
+        n.set_constants(&[1.5, 0.0, 4.0]);
+        assert_eq!(n.to_string().trim(), "Add Multiply x Float 1.5 If x Float 0 Float 4");
+
+        // Both methods find the minimum of a quadratic
+        let f = |x:&[f64]| (x[0] - 3.0).powi(2) + (x[1] + 1.0).powi(2);
+        for m in vec![tune::Method::NelderMead, tune::Method::CoordinateDescent] {
+            let (x, v) = m.minimise(f, &[0.0, 0.0], 500);
+            assert!(v < 1e-6, "{:?} {:?} {}", m, x, v);
+            assert!((x[0] - 3.0).abs() < 1e-3 && (x[1] + 1.0).abs() < 1e-3, "{:?} {:?}", m, x);
+        }
+    }
+
+    #[test]
+    fn test_pareto(){
+        let n = Node::new_from_str("Add Multiply x Float 2.0 Add y x");
+        assert_eq!(n.depth(), 3);
+        assert_eq!(n.inputs(), vec!["x".to_string(), "y".to_string()]);
+
+        // (1, 4), (2, 2) and (4, 1) are the first front.  (3, 3) is
+        // dominated by (2, 2) and (4, 4) by everything
+        let points = vec![vec![3.0, 3.0], vec![1.0, 4.0], vec![4.0, 4.0],
+                          vec![2.0, 2.0], vec![4.0, 1.0]];
+        assert!(pareto::dominates(&[2.0, 2.0], &[3.0, 3.0]));
+        assert!(!pareto::dominates(&[2.0, 2.0], &[2.0, 2.0]));
+        let mut fronts = pareto::fronts(&points[..]);
+        fronts[0].sort();
+        assert_eq!(fronts, vec![vec![1, 3, 4], vec![0], vec![2]]);
+
+        // The ends of a front are kept before the middle
+        let c = pareto::crowding(&points[..], &[1, 3, 4]);
+        assert!(c[0].is_infinite() && c[2].is_infinite() && c[1].is_finite());
+        let mut s = pareto::survivors(&points[..], 2);
+        s.sort();
+        assert_eq!(s, vec![1, 4]);
+        assert_eq!(pareto::rank(&points[..])[0].0, 1);
+    }
 
     #[test]
     fn test_node_from_string(){
@@ -3,12 +3,22 @@ pub type NodeBox = Box<Node>;
 use std::fmt;
 use std::usize;
 use std::f64;
-use rng;
+use rng::Stream;
 use inputs::Inputs;
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use units;
+use units::Unit;
+use units::UnitType;
+use cache::Cache;
+use compiled::Compiled;
+use compiled::Instruction;
 
-// The type of data that can be a terminal
+/// The type of data that can be a terminal
 #[derive(Debug, Clone)]
-enum TerminalType {
+pub enum TerminalType {
     Float(f64),
     // Custom terminals for inputs
 
@@ -16,6 +26,10 @@ enum TerminalType {
     // of writing) a f64. The string names the input field that
     // contains the value
     Inputf64(String),
+
+    // An argument passed to an automatically defined function.  Only
+    // valid in a function defining branch
+    Arg(usize),
 }
 
 // Get the data from the terminal
@@ -23,6 +37,7 @@ fn gt(tt:&TerminalType) -> String {
     match tt {
         &TerminalType::Float(f) => format!("Float {} ",f),
         &TerminalType::Inputf64(ref s) => format!("{} ",s),
+        &TerminalType::Arg(a) => format!("Arg {} ",a),
     }
 }
 
@@ -33,15 +48,14 @@ impl fmt::Display for TerminalType {
     }
 }
 
-// The operations that are implemented
+/// The operations that are implemented
 #[derive(Debug, Clone)]
-enum Operator {
+pub enum Operator {
     Add,  
     Log,  
     Multiply,
 
-    // ??! Invert 0.0?  If a individual tries to use `invert` on 0.0
-    // it will die... FIXME Is that right? 
+    // Invert 0.0 is a domain error unless the policy is protected
     Invert,
     
     Negate,
@@ -49,11 +63,245 @@ enum Operator {
     Gt, // >
     Lt, // <
     Remainder, // %
+
+    // Not in the default function set
+    Subtract,
+    Divide,
+    Sqrt,
+    Exp,
+    Sin,
+    Cos,
+    Tanh,
+    Abs,
+    Min,
+    Max,
+    Pow, // l to the power of r
+
+    // Automatically defined functions (ADFs).  A programme with ADFs
+    // is a chain of `Defun` nodes, one for each function defining
+    // branch, ending in the result producing branch.  The body of the
+    // function is in `d` and the rest of the programme is in `l`.
+    // The value is the arity of the function
+    Defun(usize),
+
+    // Call the ADF with this index.  The arguments are the children
+    // in the order they are written: `d` (arity three only), `l`,
+    // `r`
+    Adf(usize),
+
     Terminal(TerminalType),
 }
-fn _rand_f64() -> f64 {
+
+/// The branches of a programme.  The result producing branch or one
+/// of the function defining branches (by index)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Branch {
+    Result,
+    Adf(usize),
+}
+
+// The operator a name in the string representation of a tree is
+// for.  Not terminals or ADFs
+fn operator_from_str(s:&str) -> Option<Operator> {
+    match s {
+        "Add" => Some(Operator::Add),
+        "Log" => Some(Operator::Log),
+        "Multiply" => Some(Operator::Multiply),
+        "Invert" => Some(Operator::Invert),
+        "Negate" => Some(Operator::Negate),
+        "If" => Some(Operator::If),
+        "Gt" => Some(Operator::Gt),
+        "Remainder" => Some(Operator::Remainder),
+        "Lt" => Some(Operator::Lt),
+        "Subtract" => Some(Operator::Subtract),
+        "Divide" => Some(Operator::Divide),
+        "Sqrt" => Some(Operator::Sqrt),
+        "Exp" => Some(Operator::Exp),
+        "Sin" => Some(Operator::Sin),
+        "Cos" => Some(Operator::Cos),
+        "Tanh" => Some(Operator::Tanh),
+        "Abs" => Some(Operator::Abs),
+        "Min" => Some(Operator::Min),
+        "Max" => Some(Operator::Max),
+        "Pow" => Some(Operator::Pow),
+        _ => None,
+    }
+}
+
+/// The operators and terminals trees are built from, each with a
+/// relative weight for how likely it is to be chosen for a node.  The
+/// terminals are `Float` (a random constant) and `Input` (a input, or
+/// a argument in a function defining branch).  The weight of `Adf` is
+/// for each ADF that can be called
+#[derive(Debug, Clone)]
+pub struct FunctionSet {
+    // `Operator::Terminal(TerminalType::Float(_))` stands for all
+    // constants and `Operator::Terminal(TerminalType::Inputf64(_))`
+    // for all inputs
+    choices:Vec<(Operator, f64)>,
+}
+
+impl FunctionSet {
+    /// The default function set.  Every operator is as likely as a
+    /// constant and inputs are eight times as likely
+    pub fn new() -> FunctionSet {
+        FunctionSet::new_from_str("Float Log Invert Negate Multiply Gt Lt Add Remainder If Adf Input:8")
+    }
+
+    /// Make a function set from a string of `<name>:<weight>`
+    /// separated by white space.  If there is no weight it is one.
+    /// E.g.: `Add:2 Multiply:2 If Float Input:4`
+    pub fn new_from_str(s:&str) -> FunctionSet {
+        let mut choices = Vec::new();
+        for c in s.split_whitespace() {
+            let mut iter = c.split(':');
+            let o = match iter.next().unwrap() {
+                "Adf" => Operator::Adf(0),
+                "Float" => Operator::Terminal(TerminalType::Float(0.0)),
+                "Input" => Operator::Terminal(TerminalType::Inputf64(String::new())),
+                x => match operator_from_str(x) {
+                    Some(o) => o,
+                    None => panic!("Function set: Unknown operator {}", x),
+                },
+            };
+            let w = match iter.next() {
+                Some(w) => w.parse::<f64>().
+                    expect(format!("Function set: Invalid weight {}", c).as_str()),
+                None => 1.0,
+            };
+            if w > 0.0 {
+                choices.push((o, w));
+            }
+        }
+        let terminals = choices.iter().filter(|c| match c.0 {
+            Operator::Terminal(_) => true,
+            _ => false,
+        }).count();
+        if terminals == 0 {
+            panic!("Function set: Needs Float or Input: {}", s);
+        }
+        FunctionSet{choices:choices}
+    }
+
+    /// Choose a operator with probability in proportion to its
+    /// weight.  `f` multiplies the weights, zero to exclude a
+    /// operator.  A constant if nothing can be chosen
+    fn choose<F>(&self, f:F, rng:&mut Stream) -> Operator where F:Fn(&Operator) -> f64 {
+        let weights:Vec<f64> = self.choices.iter().map(|c| c.1 * f(&c.0)).collect();
+        let total:f64 = weights.iter().sum();
+        if total > 0.0 {
+            let mut x = rng.gen_range(0.0, total);
+            for i in 0..weights.len() {
+                if x < weights[i] {
+                    return self.choices[i].0.clone();
+                }
+                x -= weights[i];
+            }
+        }
+        Operator::Terminal(TerminalType::Float(0.0))
+    }
+}
+
+/// How operators that can have values that are not finite (`Invert`
+/// of zero, `Log` of a negative number...) are evaluated
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Policy {
+    /// As they are, with IEEE arithmetic.  Only a tree with a value
+    /// that is not finite fails the evaluation.  So `Gt Invert x
+    /// Float 1` is finite where `x` is zero
+    Strict,
+
+    /// Koza style protected operators.  Dividing by zero (`Invert`,
+    /// `Divide`, `Remainder`) is one.  `Log` and `Sqrt` are of the
+    /// absolute value, and `Log` of zero is zero.  `Pow` is of the
+    /// absolute value of `l`, and one if that is not finite
+    Protected,
+
+    /// As they are, but a tree that could have a value that is not
+    /// finite for inputs in the range of the data fails when it is
+    /// scored.  Found using interval arithmetic
+    Interval,
+
+    /// An argument outside the domain of any operator, or any value
+    /// that is not finite, fails the evaluation
+    Checked,
+}
+
+impl Policy {
+    pub fn new_from_str(s:&str) -> Policy {
+        match s {
+            "strict" => Policy::Strict,
+            "protected" => Policy::Protected,
+            "interval" => Policy::Interval,
+            "checked" => Policy::Checked,
+            _ => panic!("Unknown policy: {}", s),
+        }
+    }
+    pub fn to_string(&self) -> String {
+        match *self {
+            Policy::Strict => "strict",
+            Policy::Protected => "protected",
+            Policy::Interval => "interval",
+            Policy::Checked => "checked",
+        }.to_string()
+    }
+}
+
+/// Why a tree could not be evaluated.  Each names the input or
+/// operator that failed
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalError {
+    /// An input that is not in the data
+    MissingInput(String),
+
+    /// An operator without a child (or function) it needs
+    MissingChild(String),
+
+    /// An argument outside the domain of an operator (`Log` of a
+    /// negative number...).  Only under the checked policy
+    Domain(String),
+
+    /// A value that is not finite.  Under the checked policy the
+    /// operator with the value, else the root of the tree
+    NonFinite(String),
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EvalError::MissingInput(ref s) => write!(f, "Missing input {}", s),
+            EvalError::MissingChild(ref s) => write!(f, "Missing child of {}", s),
+            EvalError::Domain(ref s) => write!(f, "Domain error in {}", s),
+            EvalError::NonFinite(ref s) => write!(f, "Not finite in {}", s),
+        }
+    }
+}
+
+// The deepest level of a random tree that can have operators.  FIXME
+// Make this configurable
+const MAXLEVEL:usize = 10;
+
+/// What a random tree can be built from
+pub struct Primitives {
+    /// The names of the input fields
+    pub names:Vec<String>,
+
+    /// The arity of each automatically defined function.  Each must
+    /// be 1, 2 or 3.  Empty if ADFs are not used
+    pub adfs:Vec<usize>,
+
+    /// The unit of each input that has one
+    pub units:HashMap<String, Unit>,
+
+    /// The unit the result producing branch must have
+    pub objective:UnitType,
+
+    /// The operators and terminals
+    pub functions:FunctionSet,
+}
+fn _rand_f64(rng:&mut Stream) -> f64 {
     // Return a float that is -inf and +inf biased to numbers around 0
-    let x = rng::gen_range(0.0, 1.0) as f64;
+    let x = rng.gen_range(0.0, 1.0) as f64;
     let base = 2.0; // This is the shape of the distribution
     let scale = 10.0; // Scales results
 
@@ -84,158 +332,462 @@ impl Node {
     #[allow(dead_code)]
     pub fn new_from_str(s:&str) -> Node {
         let mut iter = s.split_whitespace();
-        Node::new_from_iter(&mut iter)
+
+        // The arity of each ADF is set by its `Defun` and needed to
+        // parse calls to it
+        let mut adfs:Vec<usize> = Vec::new();
+        Node::new_from_iter(&mut iter, &mut adfs)
     }
     #[allow(dead_code)]
-    fn new_from_iter(iter:&mut std::str::SplitWhitespace) -> Node{
+    fn new_from_iter(iter:&mut std::str::SplitWhitespace,
+                     adfs:&mut Vec<usize>) -> Node{
         
         let operator = match iter.next().unwrap() {
-            "Add" => Operator::Add,
-            "Log" => Operator::Log,
-            "Multiply" => Operator::Multiply,
-            "Invert" => Operator::Invert,
-            "Negate" => Operator::Negate,
-            "If" => Operator::If,
-            "Gt" => Operator::Gt,
-            "Remainder" => Operator::Remainder,
-            "Lt" => Operator::Lt,
+            "Defun" => {
+                let a = iter.next().unwrap().parse::<usize>().unwrap();
+                adfs.push(a);
+                Operator::Defun(a)
+            },
+            "Adf" => {
+                let k = iter.next().unwrap().parse::<usize>().unwrap();
+                if k >= adfs.len() {
+                    panic!("Call to undefined ADF: {}", k);
+                }
+                Operator::Adf(k)
+            },
+            "Arg" => {
+                let a = iter.next().unwrap().parse::<usize>().unwrap();
+                Operator::Terminal(TerminalType::Arg(a))
+            },
             "Float" =>
             {
                 let s = iter.next().unwrap();
                 let s = s.parse::<f64>().unwrap();
                 Operator::Terminal(TerminalType::Float(s))
             },
-            s => Operator::Terminal(TerminalType::Inputf64(s.to_string())),
+            s => match operator_from_str(s) {
+                Some(o) => o,
+                None => Operator::Terminal(TerminalType::Inputf64(s.to_string())),
+            },
         };
 
+        // The number of arguments to a ADF call
+        let arity = match operator {
+            Operator::Adf(k) => adfs[k],
+            _ => 0,
+        };
         let d = match operator {
-            Operator::If =>
-                Some(NodeBox::new(Node::new_from_iter(iter))),
+            Operator::If|Operator::Defun(_) =>
+                Some(NodeBox::new(Node::new_from_iter(iter, adfs))),
+            Operator::Adf(_) if arity > 2 =>
+                Some(NodeBox::new(Node::new_from_iter(iter, adfs))),
             _ => None,
         };
         let l = match operator {
             Operator::Terminal(_) => None,
-            _ => Some(NodeBox::new(Node::new_from_iter(iter))),
+            _ => Some(NodeBox::new(Node::new_from_iter(iter, adfs))),
         };
         let r = match operator {
             Operator::Add|Operator::Multiply|Operator::If|
-            Operator::Gt|Operator::Remainder|Operator::Lt =>
-                Some(NodeBox::new(Node::new_from_iter(iter))),
+            Operator::Gt|Operator::Remainder|Operator::Lt|
+            Operator::Subtract|Operator::Divide|Operator::Min|
+            Operator::Max|Operator::Pow =>
+                Some(NodeBox::new(Node::new_from_iter(iter, adfs))),
+            Operator::Adf(_) if arity > 1 =>
+                Some(NodeBox::new(Node::new_from_iter(iter, adfs))),
             _ => None,
         };
         
         Node{o:operator, l:l, r:r, d:d}
     }
 
-    // pub fn rationalise(&self) -> Node {
-    //     // let mut ret:Node;
-    //     // match self.o {
-    //     //     Operator::Terminal(ref _) => (),
-    //     //     Operator::Log => {
-    //     //         ret.l = Some(Box::new(self.l.unwrap().rationalise()));
-    //     //     },
-    //     //     Operator::Invert => {
-    //     //         ret.l = Some(Box::new(self.l.unwrap().rationalise()));
-    //     //     }
-    //     //     Operator::Negate => {
-    //     //         ret.l = Some(Box::new(self.l.unwrap().rationalise()));
-    //     //     },
-    //     //     Operator::Add => {
-    //     //         ret.l = Some(Box::new(self.l.unwrap().rationalise()));
-    //     //         ret.r = Some(Box::new(self.r.unwrap().rationalise()));
-    //     //     },
-    //     //     Operator::Multiply => {
-    //     //         ret.l = Some(Box::new(self.l.unwrap().rationalise()));
-    //     //         ret.r = Some(Box::new(self.r.unwrap().rationalise()));
-    //     //     },
-    //     //     Operator::Remainder => {
-    //     //         ret.l = Some(Box::new(self.l.unwrap().rationalise()));
-    //     //         ret.r = Some(Box::new(self.r.unwrap().rationalise()));
-    //     //     },
-    //     //     Operator::Gt => {
-    //     //         ret.l = Some(Box::new(self.l.unwrap().rationalise()));
-    //     //         ret.r = Some(Box::new(self.r.unwrap().rationalise()));
-    //     //     },
-    //     //     Operator::Lt => {
-    //     //         ret.l = Some(Box::new(self.l.unwrap().rationalise()));
-    //     //         ret.r = Some(Box::new(self.r.unwrap().rationalise()));
-    //     //     },
-    //     //     Operator::If => {
-    //     //         ret.l = Some(Box::new(self.l.unwrap().rationalise()));
-    //     //         ret.r = Some(Box::new(self.r.unwrap().rationalise()));
-    //     //         ret.d = Some(Box::new(self.d.unwrap().rationalise()));
-    //     //     },
-    //     // };
-    //     self
-    // }
+    /// A simplified copy of a tree that evaluates to the same value.
+    /// Constants are folded, `If` nodes with a constant condition (or
+    /// the same two legs) are replaced by the leg taken, `Negate
+    /// Negate x` and `Invert Invert x` become `x`, and so do `x + 0`,
+    /// `x - 0`, `x * 1`, and `x / 1`.  (`Invert Invert x` can differ
+    /// from `x` by rounding.  It is only changed under the strict and
+    /// interval policies.  `Invert Invert 0` is one under the
+    /// protected policy and fails under the checked policy)
+    pub fn rationalise(&self, policy:Policy) -> Node {
+        let child = |c:&Option<NodeBox>| match *c {
+            Some(ref c) => Some(NodeBox::new(c.rationalise(policy))),
+            None => None,
+        };
+        let ret = Node{o:self.o.clone(),
+                       l:child(&self.l),
+                       r:child(&self.r),
+                       d:child(&self.d),
+        };
+        Node::reduce_tree(ret, policy)
+    }
+
+    /// If this is a constant its value
+    fn constant(&self) -> Option<f64> {
+        match self.o {
+            Operator::Terminal(TerminalType::Float(f)) => Some(f),
+            _ => None,
+        }
+    }
+
+    // Using known facts about operators reduce the size of a tree by
+    // eliminating redundant subtrees.  The children of `node` are
+    // already reduced
+    fn reduce_tree(node:Node, policy:Policy) -> Node {
+        match node.o {
+            // Cannot reduce what is not known
+            Operator::Terminal(_)|Operator::Defun(_)|Operator::Adf(_) =>
+                return node,
+            _ => (),
+        };
+
+        // Fold constants.  Unless that makes a constant that cannot
+        // be written as a `Float`
+        if node.children().iter().all(|c| c.constant().is_some()) {
+            match node._evaluate(&Inputs::new(), policy, &[], &[]) {
+                Ok(f) if f.is_finite() =>
+                    return Node{o:Operator::Terminal(TerminalType::Float(f)),
+                                l:None, r:None, d:None},
+                _ => (),
+            }
+        }
+
+        let l = node.l.as_ref().map(|l| l.to_string());
+        let r = node.r.as_ref().map(|r| r.to_string());
+        let lc = node.l.as_ref().and_then(|l| l.constant());
+        let rc = node.r.as_ref().and_then(|r| r.constant());
+        match node.o {
+            Operator::If => {
+                // For a `If` node check the condition.  If it is
+                // constant replace the If node with the leg taken.
+                // If both legs are the same it does not matter
+                match node.d.as_ref().unwrap().constant() {
+                    Some(f) if f > 0.0 => *node.l.unwrap(),
+                    Some(_) => *node.r.unwrap(),
+                    None if l == r => *node.l.unwrap(),
+                    None => node,
+                }
+            },
+            Operator::Negate|Operator::Invert => {
+                // Is the child the same operator?
+                let twice = match (&node.o, &node.l.as_ref().unwrap().o) {
+                    (&Operator::Negate, &Operator::Negate) => true,
+                    (&Operator::Invert, &Operator::Invert) =>
+                        policy == Policy::Strict || policy == Policy::Interval,
+                    _ => false,
+                };
+                if twice {
+                    *node.l.unwrap().l.unwrap()
+                }else{
+                    node
+                }
+            },
+            Operator::Add => match (lc, rc) {
+                (Some(f), _) if f == 0.0 => *node.r.unwrap(),
+                (_, Some(f)) if f == 0.0 => *node.l.unwrap(),
+                _ => node,
+            },
+            Operator::Multiply => match (lc, rc) {
+                (Some(f), _) if f == 1.0 => *node.r.unwrap(),
+                (_, Some(f)) if f == 1.0 => *node.l.unwrap(),
+                _ => node,
+            },
+            Operator::Subtract => match rc {
+                Some(f) if f == 0.0 => *node.l.unwrap(),
+                _ => node,
+            },
+            Operator::Divide => match rc {
+                Some(f) if f == 1.0 => *node.l.unwrap(),
+                _ => node,
+            },
+            _ => node,
+        }
+    }
+
+    /// Build a random programme.  A function defining branch for
+    /// each ADF in `prims` followed by the result producing branch.
+    /// ADFs are dimensionless.  With no ADFs this is the same as
+    /// `Node::new(prims, Branch::Result, 0, &prims.objective, rng)`,
+    /// retried until it type checks
+    pub fn new_program(prims:&Primitives, rng:&mut Stream) -> Node {
+        // A tree can come out in the wrong unit if no input has a
+        // unit it needs
+        for _ in 0..1000 {
+            let mut ret = Node::new(prims, Branch::Result, 0, &prims.objective, rng);
+            for k in (0..prims.adfs.len()).rev() {
+                let body = Node::new(prims, Branch::Adf(k), 0,
+                                     &Some(Unit::dimensionless()), rng);
+                ret = Node{o:Operator::Defun(prims.adfs[k]),
+                           l:Some(NodeBox::new(ret)),
+                           r:None,
+                           d:Some(NodeBox::new(body)),
+                };
+            }
+            if ret.type_check(prims) {
+                return ret;
+            }
+        }
+        panic!("Cannot build a tree in the unit of the objective");
+    }
 
     // Build a random tree
     /* Paramaters:
-     * names - The names of the input fields
+     * prims - The inputs and ADFs that can be used
+     * branch - The branch of the programme this tree is for.  The
+     *          result producing branch can call ADFs.  A function
+     *          defining branch can use its arguments
      * level - The distance from the root node for this node
+     * unit - The unit the tree must have.  `None` for any unit
+     * rng - Where the random choices come from
      */
-    pub fn new(names:&Vec<String>, level:usize) -> Node {
+    pub fn new(prims:&Primitives, branch:Branch, level:usize,
+               unit:&UnitType, rng:&mut Stream) -> Node {
         let l = level+1;
 
         macro_rules! NewNode {
-            // Create a Node (in a Box).  The first argument is the
-            // name of the node operator the second is 1,2, or 3 that
-            // sets the number of children.  (FIXME The number of
-            // children depends on the operator so $c depends on
-            // $name)
-            ($name:ident, $c:expr) => {
+            // Create a Node.  The first argument is the name of the
+            // node operator the rest are the units of the children
+            // `l`, `r` and `d`.  The unit of `r` is a closure that
+            // is passed the unit of `l`, so it can depend on it
+            ($name:ident, $lu:expr) => {
                 {
-                    let mut ret = Node{o:Operator::$name,
-                                       l: None,
-                                       r: None,
-                                       d: None,
-                    };
-                    if $c > 0 {
-                        ret.l = Some(Box::new(Node::new(names, l)));
-                    }
-                    if $c > 1 {
-                        ret.r = Some(Box::new(Node::new(names, l)));
+                    let ln = Node::new(prims, branch, l, &$lu, rng);
+                    Node{o:Operator::$name,
+                         l: Some(Box::new(ln)),
+                         r: None,
+                         d: None,
                     }
-                    if $c > 2 {
-                        ret.d = Some(Box::new(Node::new(names, l)));
+                }
+            };
+            ($name:ident, $lu:expr, $ru:expr) => {
+                {
+                    let ln = Node::new(prims, branch, l, &$lu, rng);
+                    let lu = ln.unit(&prims.units).unwrap_or(None);
+                    let rn = Node::new(prims, branch, l, &$ru(lu), rng);
+                    Node{o:Operator::$name,
+                         l: Some(Box::new(ln)),
+                         r: Some(Box::new(rn)),
+                         d: None,
                     }
+                }
+            };
+            ($name:ident, $lu:expr, $ru:expr, $du:expr) => {
+                {
+                    let mut ret = NewNode!($name, $lu, $ru);
+                    ret.d = Some(Box::new(Node::new(prims, branch, l, &$du, rng)));
                     ret
                 }
-            }
+            };
         };
 
-        // FIXME Make this max level a configurable constant
-        let maxlevel = 10;
-        let a = if level > maxlevel { 
-            0
-        }else{
-            //  This controlls how many input nodes there are
-            //  (probabilistically). For a <= 9 ithe node will be a
-            //  internal function (there are ten internal node types:
-            //  0 => constant to 9 => if.  FIXME Make nmax
-            //  configurable
-            let nmax = 18;
-            rng::gen_range(0, nmax)
-        };
-        match a {
-            0 => Node{o:Operator::Terminal(TerminalType::Float(_rand_f64())), l:None, r:None, d:None},
-            1 => NewNode!(Log,1),
-            2 => NewNode!(Invert,1),
-            3 => NewNode!(Negate,1),
-            4 => NewNode!(Multiply,2),
-            5 => NewNode!(Gt,2),
-            6 => NewNode!(Lt,2),
-            7 => NewNode!(Add,2),
-            8 => NewNode!(Remainder,2),
-            9 => NewNode!(If,3),
-            _ => {
-                // Input node
-                let n = names.len();
-                let b = rng::gen_range(0, n);
-                let s = names[b].clone();
-                Node{o:Operator::Terminal(TerminalType::Inputf64(s)), l:None, r:None, d:None}
+        // The number of ADFs that can be called and the number of
+        // arguments that can be used in this branch
+        let (ncall, narg) = match branch {
+            Branch::Result => (prims.adfs.len(), 0),
+            Branch::Adf(k) => (0, prims.adfs[k]),
+        };
+
+        // Can this tree be dimensionless?  If not some operators
+        // cannot be used
+        let dimensionless = units::dimensionless(unit).is_ok();
+
+        // Constants, inputs with no unit, ADF arguments and calls to
+        // ADFs are dimensionless in a product, so they are only used
+        // where a dimensionless value will do
+        let (ncall, narg) = if dimensionless {(ncall, narg)} else {(0, 0)};
+
+        // The unit of `r` when it must be the same as `l`
+        let same = |lu:UnitType| match *unit {
+            Some(_) => unit.clone(),
+            None => lu,
+        };
+
+        // The inputs with the right unit
+        let names:Vec<&String> = prims.names.iter().filter(|n| {
+            match prims.units.get(*n) {
+                Some(u) => units::same(unit, &Some(u.clone())).is_ok(),
+                None => dimensionless,
+            }
+        }).collect();
+
+
+        // Choose what this node is from the function set.  Only
+        // terminals below the maximum level.  Only what can be used
+        // in this branch with this unit.
+        let o = prims.functions.choose(|o| match *o {
+            Operator::Terminal(TerminalType::Float(_)) =>
+                if dimensionless {1.0} else {0.0},
+            Operator::Terminal(_) =>
+                if names.len() + narg > 0 {1.0} else {0.0},
+            _ if level > MAXLEVEL => 0.0,
+
+            // Weight is for each ADF
+            Operator::Adf(_) => ncall as f64,
+
+            // Results are dimensionless
+            Operator::Log|Operator::Gt|Operator::Lt|Operator::Exp|
+            Operator::Sin|Operator::Cos|Operator::Tanh|
+            Operator::Pow if !dimensionless => 0.0,
+            _ => 1.0,
+        }, rng);
+        match o {
+            Operator::Log => NewNode!(Log, Some(Unit::dimensionless())),
+            Operator::Invert => NewNode!(Invert, unit.as_ref().map(|u| u.invert())),
+            Operator::Negate => NewNode!(Negate, unit.clone()),
+            Operator::Multiply => NewNode!(Multiply, None, |lu:UnitType| match *unit {
+                Some(ref u) => Some(u.multiply(&lu.unwrap_or(Unit::dimensionless()).invert())),
+                None => None,
+            }),
+            Operator::Gt => NewNode!(Gt, None, |lu| lu),
+            Operator::Lt => NewNode!(Lt, None, |lu| lu),
+            Operator::Add => NewNode!(Add, unit.clone(), same),
+            Operator::Remainder => NewNode!(Remainder, unit.clone(), same),
+            Operator::If => NewNode!(If, unit.clone(), same, None),
+            Operator::Subtract => NewNode!(Subtract, unit.clone(), same),
+            Operator::Min => NewNode!(Min, unit.clone(), same),
+            Operator::Max => NewNode!(Max, unit.clone(), same),
+            Operator::Divide => NewNode!(Divide, None, |lu:UnitType| match *unit {
+                Some(ref u) => Some(lu.unwrap_or(Unit::dimensionless()).multiply(&u.invert())),
+                None => None,
+            }),
+            Operator::Sqrt => {
+                // If any unit will do, a dimensionless child is sure to
+                // have a square root
+                let u = match *unit {
+                    Some(ref u) => u.multiply(u),
+                    None => Unit::dimensionless(),
+                };
+                NewNode!(Sqrt, Some(u))
+            },
+            Operator::Exp => NewNode!(Exp, Some(Unit::dimensionless())),
+            Operator::Sin => NewNode!(Sin, Some(Unit::dimensionless())),
+            Operator::Cos => NewNode!(Cos, Some(Unit::dimensionless())),
+            Operator::Tanh => NewNode!(Tanh, Some(Unit::dimensionless())),
+            Operator::Abs => NewNode!(Abs, unit.clone()),
+            Operator::Pow => NewNode!(Pow, Some(Unit::dimensionless()),
+                                      |_| Some(Unit::dimensionless())),
+            Operator::Adf(_) => {
+                // Call a ADF.  The arguments are dimensionless
+                let k = rng.gen_range(0, ncall);
+                let mut ret = Node{o:Operator::Adf(k), l:None, r:None, d:None};
+                let du = Some(Unit::dimensionless());
+                if prims.adfs[k] > 0 {
+                    ret.l = Some(Box::new(Node::new(prims, branch, l, &du, rng)));
+                }
+                if prims.adfs[k] > 1 {
+                    ret.r = Some(Box::new(Node::new(prims, branch, l, &du, rng)));
+                }
+                if prims.adfs[k] > 2 {
+                    ret.d = Some(Box::new(Node::new(prims, branch, l, &du, rng)));
+                }
+                ret
+            },
+            Operator::Terminal(TerminalType::Inputf64(_)) => {
+                // Input node, or argument node in a function defining
+                // branch
+                let n = names.len() + narg;
+                let b = rng.gen_range(0, n);
+                let t = if b < names.len() {
+                    TerminalType::Inputf64(names[b].clone())
+                }else{
+                    TerminalType::Arg(b - names.len())
+                };
+                Node{o:Operator::Terminal(t), l:None, r:None, d:None}
+            },
+            _ => Node{o:Operator::Terminal(TerminalType::Float(_rand_f64(rng))), l:None, r:None, d:None},
+        }
+    }
+
+    /// The unit of the value of a tree, given the units of the
+    /// inputs.  `Err` if the tree combines values in units that
+    /// cannot be combined.  ADF arguments are dimensionless and a
+    /// call to a ADF has the unit of its body.  In a tree with no
+    /// function defining branches, like a subtree of a programme, a
+    /// call is taken to be dimensionless, as new ADFs are made
+    pub fn unit(&self, units:&HashMap<String, Unit>) -> Result<UnitType, String> {
+        let (adfs, node) = self.branches();
+        let mut adf_units:Vec<UnitType> = Vec::new();
+        for a in adfs {
+            // A body can only call the ADFs before it
+            let u = a._unit(units, &adf_units[..])?;
+            adf_units.push(u);
+        }
+        node._unit(units, &adf_units[..])
+    }
+
+    // The unit of a branch.  This is where the units each operator
+    // accepts and returns are defined
+    fn _unit(&self, units:&HashMap<String, Unit>,
+             adfs:&[UnitType]) -> Result<UnitType, String> {
+        macro_rules! unit {
+            ($a:ident) => {
+                match self.$a {
+                    Some(ref $a) => $a._unit(units, adfs)?,
+                    None => panic!("Missing child"),
+                }
             }
         }
+        match self.o {
+            Operator::Terminal(TerminalType::Float(_)) => Ok(None),
+            Operator::Terminal(TerminalType::Arg(_)) => Ok(Some(Unit::dimensionless())),
+            Operator::Terminal(TerminalType::Inputf64(ref s)) =>
+                Ok(units.get(s).cloned()),
+            Operator::Log => {
+                units::dimensionless(&unit!(l))?;
+                Ok(Some(Unit::dimensionless()))
+            },
+            Operator::Invert => Ok(unit!(l).map(|u| u.invert())),
+            Operator::Negate => Ok(unit!(l)),
+            Operator::Multiply => Ok(units::product(&unit!(l), &unit!(r))),
+            Operator::Gt|Operator::Lt => {
+                units::same(&unit!(l), &unit!(r))?;
+                Ok(Some(Unit::dimensionless()))
+            },
+            Operator::Add|Operator::Remainder|Operator::Subtract|
+            Operator::Min|Operator::Max =>
+                units::same(&unit!(l), &unit!(r)),
+            Operator::Divide => {
+                let r = unit!(r).map(|u| u.invert());
+                Ok(units::product(&unit!(l), &r))
+            },
+            Operator::Sqrt => match unit!(l) {
+                Some(u) => match u.sqrt() {
+                    Some(u) => Ok(Some(u)),
+                    None => Err(format!("Sqrt of {}", u.to_string())),
+                },
+                None => Ok(None),
+            },
+            Operator::Exp|Operator::Sin|Operator::Cos|Operator::Tanh => {
+                units::dimensionless(&unit!(l))?;
+                Ok(Some(Unit::dimensionless()))
+            },
+            Operator::Pow => {
+                units::dimensionless(&unit!(l))?;
+                units::dimensionless(&unit!(r))?;
+                Ok(Some(Unit::dimensionless()))
+            },
+            Operator::Abs => Ok(unit!(l)),
+            Operator::If => {
+                unit!(d);
+                units::same(&unit!(l), &unit!(r))
+            },
+            Operator::Adf(k) => {
+                for c in self.children() {
+                    units::dimensionless(&c._unit(units, adfs)?)?;
+                }
+                Ok(adfs.get(k).cloned().unwrap_or(Some(Unit::dimensionless())))
+            },
+            Operator::Defun(_) => panic!("Defun inside a branch"),
+        }
+    }
+
+    /// Check a programme combines inputs in units that make sense and
+    /// its value is in the unit of the objective
+    pub fn type_check(&self, prims:&Primitives) -> bool {
+        match self.unit(&prims.units) {
+            Ok(u) => units::same(&u, &prims.objective).is_ok(),
+            Err(_) => false,
+        }
     }
 
     /// Recursive count of child nodes
@@ -256,131 +808,216 @@ impl Node {
         dc + lc + rc + 1
     }
 
-    /// Select a random node from the tree
-    pub fn random_node(&self) -> NodeBox {
-        // Choose a subtree (node) of this tree (node).  FIXME there
-        // is a lot of optimisation to be done.  Paticularly if each
-        // node had the number of nodes that are child nodes of this...
+    /// The number of nodes on the longest path from this node to a
+    /// terminal
+    pub fn depth(&self) -> usize {
+        self.children().iter().map(|c| c.depth()).max().unwrap_or(0) + 1
+    }
 
-        // Select the node by first counting all nodes in the tree
-        // then randomly selecting a number < the total
-        let c = self.count_nodes();
-        let mut n = rng::gen_range(0, c);
-        
-        // At the end of the loop `node` will be the selected Node.
-        let mut node:& Node = self; 
-        loop {
+    /// The names of the inputs used, each once, in order
+    pub fn inputs(&self) -> Vec<String> {
+        let mut ret = Vec::new();
+        self._inputs(&mut ret);
+        ret.sort();
+        ret.dedup();
+        ret
+    }
+    fn _inputs(&self, ret:&mut Vec<String>) {
+        if let Operator::Terminal(TerminalType::Inputf64(ref s)) = self.o {
+            ret.push(s.clone());
+        }
+        for c in self.children() {
+            c._inputs(ret);
+        }
+    }
 
-            // Loop invariant n >= 0 Exit when a node with no left or
-            // right children is encountered or n == 0
-            if n == 0 {
-                break;
+    /// The children of this node in the order they are written: `d`,
+    /// `l`, `r`
+    pub fn children(&self) -> Vec<&NodeBox> {
+        let mut ret = Vec::new();
+        for c in [&self.d, &self.l, &self.r].iter() {
+            if let Some(ref n) = **c {
+                ret.push(n);
             }
+        }
+        ret
+    }
 
-            // Check for children
-            let fl = match node.l{Some(_) => false, None => true};
-            let fr = match node.r{Some(_) => false, None => true};
-            if fl && fr {
-                // No children (if there is a 'd' child there must be
-                // 'l'and 'r') or n is one so we have arrived at the
-                // node selected by the e.gen_range statement above
+    /// Every node that can be a crossover or mutation point, with the
+    /// branch of the programme it is in.  Nodes are identified by
+    /// their position in the string representation (the root is 0).
+    /// `Defun` nodes are structure and are not included
+    pub fn points(&self) -> Vec<(usize, Branch)> {
+        let mut ret = Vec::new();
 
-                // FIXME n cannot be zero.  The comment above talks of
-                // n == 1.  Why?
-                if n != 0 {
-                    panic!("Node: {} n {}", self.to_string(), n);
+        // `n` is the position of `node`
+        let mut n = 0;
+        let mut k = 0;
+        let mut node:&Node = self;
+        loop {
+            if let Operator::Defun(_) = node.o {
+                let c = node.d.as_ref().unwrap().count_nodes();
+                for i in 0..c {
+                    ret.push((n + 1 + i, Branch::Adf(k)));
+                }
+                n += 1 + c;
+                k += 1;
+                node = node.l.as_ref().unwrap();
+            }else{
+                for i in 0..node.count_nodes() {
+                    ret.push((n + i, Branch::Result));
                 }
                 break;
             }
+        }
+        ret
+    }
 
-            // Children in decision node
-            let dc = match node.d {
-                Some(ref d) => (*d).count_nodes(),
-                None => 0,
-            };
-
-            if dc >= n {
-                // Wanted node is in decision sub-tree
-
-                if let Some(ref nd) = node.d {
-                    node = &*nd;
+    /// Get the node at position `n` (see `points`)
+    pub fn get_node(&self, n:usize) -> &Node {
+        if n == 0 {
+            return self;
+        }
+        let mut n = n - 1;
+        for c in self.children() {
+            let cc = c.count_nodes();
+            if n < cc {
+                return c.get_node(n);
+            }
+            n -= cc;
+        }
+        panic!("Node: {} has no node {}", self.to_string(), n);
+    }
 
-                    // Subtract the current (consumed) node from n
-                    n -= 1;
-                    
-                    continue // FIXME is this needed?
-                }else{
-                    // There is no decision node.  This code should
-                    // never be reached.
-                    panic!("dc {} n {} operator {:?} ",
-                           dc, n, self.to_string());
+    /// Replace the node at position `n` (see `points`)
+    pub fn replace_node(&mut self, n:usize, node:NodeBox) {
+        if n == 0 {
+            *self = *node;
+            return;
+        }
+        let mut n = n - 1;
+        for c in [&mut self.d, &mut self.l, &mut self.r].iter_mut() {
+            if let Some(ref mut c) = **c {
+                let cc = c.count_nodes();
+                if n < cc {
+                    c.replace_node(n, node);
+                    return;
                 }
-            } // dc >= n
+                n -= cc;
+            }
+        }
+        panic!("Cannot replace node {}", n);
+    }
 
-            // Is node in left subtree?
-            let lc = match node.l {
-                Some(ref q) => (*q).count_nodes(),
-                None => 0,
-            };
+    /// Select a random node from a branch of the programme.  `None`
+    /// if this programme does not have that branch
+    pub fn random_node(&self, branch:Branch, rng:&mut Stream) -> Option<NodeBox> {
+        let points:Vec<usize> = self.points().iter().
+            filter(|p| p.1 == branch).map(|p| p.0).collect();
+        if points.len() == 0 {
+            None
+        }else{
+            let n = points[rng.gen_range(0, points.len())];
+            Some(self.get_node(n).copy())
+        }
+    }
 
-            if dc+lc >= n {
-                // Get node from left sub tree
+    /// The values of the constants in a tree, in the order they are
+    /// written
+    pub fn constants(&self) -> Vec<f64> {
+        let mut ret = Vec::new();
+        self._constants(&mut ret);
+        ret
+    }
+    fn _constants(&self, ret:&mut Vec<f64>) {
+        if let Operator::Terminal(TerminalType::Float(f)) = self.o {
+            ret.push(f);
+        }
+        for c in self.children() {
+            c._constants(ret);
+        }
+    }
 
-                if let Some(ref nd) =  node.l {
-                    node = &*nd;
+    /// Set the constants in a tree to these values.  In the order
+    /// `constants` returns them
+    pub fn set_constants(&mut self, values:&[f64]) {
+        let mut iter = values.iter();
+        self._set_constants(&mut iter);
+    }
+    fn _set_constants(&mut self, iter:&mut std::slice::Iter<f64>) {
+        if let Operator::Terminal(TerminalType::Float(_)) = self.o {
+            self.o = Operator::Terminal(TerminalType::Float(
+                *iter.next().expect("Too few constants")));
+        }
+        for c in vec![&mut self.d, &mut self.l, &mut self.r] {
+            if let Some(ref mut c) = *c {
+                c._set_constants(iter);
+            }
+        }
+    }
 
-                    // Have consumed all nodes in decision tree
-                    // and this node.
-                    n -= 1+dc;
-                    
-                    continue // FIXME is this needed?
-                }else{
-                    // There is no left sub-tree.  This code should
-                    // never be reached.
-                    panic!("lc {} n {} operator {:?} ",
-                           lc, n, self.to_string())
-                }
+    /// Replace the operator of node `n` with another from the
+    /// function set that takes as many arguments (`Add` for
+    /// `Multiply`, `Gt` for `Lt`...).  The children are kept.  False
+    /// if there is no other
+    pub fn point_mutate(&mut self, n:usize, prims:&Primitives, rng:&mut Stream) -> bool {
+        // Operators with one or two arguments can be swapped
+        let arity = |o:&Operator| match *o {
+            Operator::Terminal(_)|Operator::Adf(_)|Operator::Defun(_)|
+            Operator::If => 0,
+            ref o if o.unary() => 1,
+            _ => 2,
+        };
+        let mut node = self.get_node(n).copy();
+        let a = arity(&node.o);
+        if a == 0 {
+            return false;
+        }
+        let o = prims.functions.choose(|o| {
+            if arity(o) == a && mem::discriminant(o) != mem::discriminant(&node.o) {
+                1.0
             }else{
+                0.0
+            }
+        }, rng);
+        if arity(&o) != a {
+            // Nothing to choose from
+            return false;
+        }
+        node.o = o;
+        self.replace_node(n, node);
+        true
+    }
 
-                // Get node from right subtree
-                if let Some(ref nd) = node.r {
-                    node = &*nd;
-
-                    // Have consumed all nodes in decision tree,
-                    // left sub-tree, and this node.
-                    n -= 1+dc+lc;
+    /// A random terminal for `branch` with the unit `unit`
+    pub fn new_leaf(prims:&Primitives, branch:Branch, unit:&UnitType,
+                    rng:&mut Stream) -> Node {
+        // Only terminals are built below the maximum level
+        Node::new(prims, branch, MAXLEVEL + 1, unit, rng)
+    }
 
-                    continue // FIXME is this needed?
-                }else{
-                    // There is no right sub tree.  This code should
-                    // never be reached.
-                    panic!("n {} operator {:?} ",
-                           n, self.to_string())
-                }
-            }
+    /// The terminal this node is, if it is one
+    pub fn terminal(&self) -> Option<&TerminalType> {
+        match self.o {
+            Operator::Terminal(ref t) => Some(t),
+            _ => None,
         }
-        NodeBox::new(*node.copy())
+    }
+
+    /// A terminal node
+    pub fn new_terminal(t:TerminalType) -> Node {
+        Node{o:Operator::Terminal(t), l:None, r:None, d:None}
+    }
+
+    /// A random constant, as `new` makes them
+    pub fn new_constant(rng:&mut Stream) -> Node {
+        Node::new_terminal(TerminalType::Float(_rand_f64(rng)))
     }
 
     /// A recursive copy of a Node
     pub fn copy(&self) -> NodeBox {
         let ret = Node{
-            // FIXME Why not: o:self.o,
-            o:match self.o {
-                Operator::Add => Operator::Add,
-                Operator::Log => Operator::Log,
-                Operator::Multiply => Operator::Multiply,
-                Operator::Invert => Operator::Invert,
-                Operator::Negate => Operator::Negate,
-                Operator::Remainder => Operator::Remainder,
-                Operator::If => Operator::If,
-                Operator::Gt => Operator::Gt,
-                Operator::Lt => Operator::Lt,
-                Operator::Terminal(ref t) => match *t{
-                    TerminalType::Float(f) => Operator::Terminal(TerminalType::Float(f)),
-                    TerminalType::Inputf64(ref s) => Operator::Terminal(TerminalType::Inputf64(s.clone())),
-                },
-            },
+            o:self.o.clone(),
             l:match self.l {
                 Some(ref l) => Some(l.copy()),
                 None => None,
@@ -461,6 +1098,28 @@ impl Node {
             Operator::Remainder => node_to_string2!(Remainder),
             Operator::Log => node_to_string1!(Log),
             Operator::Invert => node_to_string1!(Invert),
+            Operator::Subtract => node_to_string2!(Subtract),
+            Operator::Divide => node_to_string2!(Divide),
+            Operator::Sqrt => node_to_string1!(Sqrt),
+            Operator::Exp => node_to_string1!(Exp),
+            Operator::Sin => node_to_string1!(Sin),
+            Operator::Cos => node_to_string1!(Cos),
+            Operator::Tanh => node_to_string1!(Tanh),
+            Operator::Abs => node_to_string1!(Abs),
+            Operator::Min => node_to_string2!(Min),
+            Operator::Max => node_to_string2!(Max),
+            Operator::Pow => node_to_string2!(Pow),
+            Operator::Defun(a) => {
+                ret.push_str(&format!("Defun {} ", a));
+                child_to_string!(d);
+                child_to_string!(l);
+            },
+            Operator::Adf(k) => {
+                ret.push_str(&format!("Adf {} ", k));
+                for c in self.children() {
+                    ret.push_str(&c.to_string());
+                }
+            },
             Operator::Terminal(ref f) => {
                 ret.push_str(&format!("{}", f));
             },
@@ -537,6 +1196,40 @@ impl Node {
             Operator::Remainder => node_to_string2!(Remainder),
             Operator::Log => node_to_string1!(Log),
             Operator::Invert => node_to_string1!(Invert),
+            Operator::Subtract => node_to_string2!(Subtract),
+            Operator::Divide => node_to_string2!(Divide),
+            Operator::Sqrt => node_to_string1!(Sqrt),
+            Operator::Exp => node_to_string1!(Exp),
+            Operator::Sin => node_to_string1!(Sin),
+            Operator::Cos => node_to_string1!(Cos),
+            Operator::Tanh => node_to_string1!(Tanh),
+            Operator::Abs => node_to_string1!(Abs),
+            Operator::Min => node_to_string2!(Min),
+            Operator::Max => node_to_string2!(Max),
+            Operator::Pow => node_to_string2!(Pow),
+            Operator::Defun(a) => {
+                for _ in 0..level {
+                    ret.push_str(sp);
+                }
+                ret.push_str(&format!("Defun {}\n", a));
+                child_to_string!(d);
+
+                // The rest of the programme is not nested in this
+                // function
+                match self.l {
+                    Some(ref l) => ret.push_str(&l.to_pretty_string(level)),
+                    None => panic!("name invalid"),
+                };
+            },
+            Operator::Adf(k) => {
+                for _ in 0..level {
+                    ret.push_str(sp);
+                }
+                ret.push_str(&format!("Adf {}\n", k));
+                for c in self.children() {
+                    ret.push_str(&c.to_pretty_string(level+1));
+                }
+            },
             Operator::Terminal(ref f) => {
                 for _ in 0..level {
                     ret.push_str(sp);
@@ -547,160 +1240,604 @@ impl Node {
         ret
     }
 
+    /// The function defining branches of a programme and its result
+    /// producing branch
+    fn branches(&self) -> (Vec<&Node>, &Node) {
+        let mut adfs:Vec<&Node> = Vec::new();
+        let mut node:&Node = self;
+        while let Operator::Defun(_) = node.o {
+            adfs.push(node.d.as_ref().unwrap());
+            node = node.l.as_ref().unwrap();
+        }
+        (adfs, node)
+    }
+
+    /// Evaluate a programme over a set of inputs.  The function
+    /// defining branches are collected and the result producing
+    /// branch evaluated
+    #[allow(dead_code)]
+    pub fn evaluate(&self, inputs:&Inputs, policy:Policy)->Result<f64, EvalError> {
+        let (adfs, node) = self.branches();
+        let ret = node._evaluate(inputs, policy, &adfs[..], &[])?;
+        if ret.is_finite() {
+            Ok(ret)
+        }else{
+            Err(EvalError::NonFinite(format!("{:?}", node.o)))
+        }
+    }
+
+    /// The range of values of a programme when each input is in the
+    /// range given.  `None` if it could have a value that is not
+    /// finite.  Used by the interval policy
+    pub fn interval(&self, ranges:&HashMap<String, (f64, f64)>) -> Option<(f64, f64)> {
+        let (adfs, node) = self.branches();
+        node._interval(ranges, &adfs[..], &[])
+    }
+
+    /// Recursively find the range of values of a tree using interval
+    /// arithmetic.  The range of each operator is the range of the
+    /// values it can have for arguments in the ranges of its children.
+    /// Ranges are conservative (can be larger than needed)
+    fn _interval(&self, ranges:&HashMap<String, (f64, f64)>,
+                 adfs:&[&Node], args:&[(f64, f64)]) -> Option<(f64, f64)> {
+        macro_rules! interval {
+            ($a:ident) => {
+                match self.$a {
+                    Some(ref $a) => $a._interval(ranges, adfs, args)?,
+                    None => panic!("Missing child"),
+                }
+            }
+        }
+
+        // The smallest range with all these values
+        fn hull(v:&[f64]) -> (f64, f64) {
+            v.iter().fold((f64::INFINITY, f64::NEG_INFINITY),
+                          |r, x| (r.0.min(*x), r.1.max(*x)))
+        }
+
+        // Does the range include zero?
+        let zero = |a:(f64, f64)| a.0 <= 0.0 && a.1 >= 0.0;
+
+        let ret = match self.o {
+            Operator::Terminal(TerminalType::Float(f)) => (f, f),
+            Operator::Terminal(TerminalType::Inputf64(ref s)) => *ranges.get(s)?,
+            Operator::Terminal(TerminalType::Arg(a)) => *args.get(a)?,
+            Operator::Adf(k) => {
+                let mut a:Vec<(f64, f64)> = Vec::new();
+                for c in self.children() {
+                    a.push(c._interval(ranges, adfs, args)?);
+                }
+                adfs[k]._interval(ranges, &adfs[..k], &a[..])?
+            },
+            Operator::Defun(_) => panic!("Defun inside a branch"),
+            Operator::If => {
+                // Both legs can be taken
+                interval!(d);
+                let l = interval!(l);
+                let r = interval!(r);
+                (l.0.min(r.0), l.1.max(r.1))
+            },
+            Operator::Gt|Operator::Lt => {
+                interval!(l);
+                interval!(r);
+                (-1.0, 1.0)
+            },
+            Operator::Add => {
+                let l = interval!(l);
+                let r = interval!(r);
+                (l.0 + r.0, l.1 + r.1)
+            },
+            Operator::Subtract => {
+                let l = interval!(l);
+                let r = interval!(r);
+                (l.0 - r.1, l.1 - r.0)
+            },
+            Operator::Multiply => {
+                let l = interval!(l);
+                let r = interval!(r);
+                hull(&[l.0*r.0, l.0*r.1, l.1*r.0, l.1*r.1])
+            },
+            Operator::Negate => {
+                let l = interval!(l);
+                (-l.1, -l.0)
+            },
+            Operator::Invert => {
+                let l = interval!(l);
+                if zero(l) {
+                    return None;
+                }
+                hull(&[1.0/l.0, 1.0/l.1])
+            },
+            Operator::Divide => {
+                let l = interval!(l);
+                let r = interval!(r);
+                if zero(r) {
+                    return None;
+                }
+                hull(&[l.0/r.0, l.0/r.1, l.1/r.0, l.1/r.1])
+            },
+            Operator::Remainder => {
+                // Smaller in magnitude than the divisor
+                interval!(l);
+                let r = interval!(r);
+                if zero(r) {
+                    return None;
+                }
+                let m = r.0.abs().max(r.1.abs());
+                (-m, m)
+            },
+            Operator::Log => {
+                let l = interval!(l);
+                if l.0 <= 0.0 {
+                    return None;
+                }
+                (l.0.ln(), l.1.ln())
+            },
+            Operator::Sqrt => {
+                let l = interval!(l);
+                if l.0 < 0.0 {
+                    return None;
+                }
+                (l.0.sqrt(), l.1.sqrt())
+            },
+            Operator::Exp => {
+                let l = interval!(l);
+                (l.0.exp(), l.1.exp())
+            },
+            Operator::Sin|Operator::Cos => {
+                interval!(l);
+                (-1.0, 1.0)
+            },
+            Operator::Tanh => {
+                let l = interval!(l);
+                (l.0.tanh(), l.1.tanh())
+            },
+            Operator::Abs => {
+                let l = interval!(l);
+                if l.0 >= 0.0 {
+                    l
+                }else if l.1 <= 0.0 {
+                    (-l.1, -l.0)
+                }else{
+                    (0.0, l.1.max(-l.0))
+                }
+            },
+            Operator::Min => {
+                let l = interval!(l);
+                let r = interval!(r);
+                (l.0.min(r.0), l.1.min(r.1))
+            },
+            Operator::Max => {
+                let l = interval!(l);
+                let r = interval!(r);
+                (l.0.max(r.0), l.1.max(r.1))
+            },
+            Operator::Pow => {
+                // Only for a positive base where it is monotonic in
+                // both arguments
+                let l = interval!(l);
+                let r = interval!(r);
+                if l.0 <= 0.0 {
+                    return None;
+                }
+                hull(&[l.0.powf(r.0), l.0.powf(r.1), l.1.powf(r.0), l.1.powf(r.1)])
+            },
+        };
+        if ret.0.is_finite() && ret.1.is_finite() {
+            Some(ret)
+        }else{
+            None
+        }
+    }
+
     /// Recursively evaluate a tree over a set of inputs.  This is
-    /// where operators are defined.
-    pub fn evaluate(&self, inputs:&Inputs)->Option<f64> {
-        // FIXME Should return a result
+    /// where operators are defined.  `adfs` are the bodies of the
+    /// ADFs that can be called and `args` the arguments if this is in
+    /// a function defining branch
+    fn _evaluate(&self, inputs:&Inputs, policy:Policy,
+                 adfs:&[&Node], args:&[f64])->Result<f64, EvalError> {
         macro_rules! evaluate {
             ($a:ident) => {
                 match self.$a {
-                    Some(ref $a) => {
-                        let n = &(*$a); // Node.  FIXME  Comment this &(*$a)
-                        let f = n.evaluate(inputs); // Option<f64>
-                        let l = f.unwrap();
-                        l
-                    },
-                    None => panic!("Missing child") ,
+                    Some(ref $a) => $a._evaluate(inputs, policy, adfs, args)?,
+                    None => return Err(EvalError::MissingChild(format!("{:?}", self.o))),
                 }
             }
         }
 
         let ret = match self.o {
-            Operator::Terminal(TerminalType::Float(f)) => Some(f),
-            Operator::Terminal(TerminalType::Inputf64(ref s)) => Some(*(inputs.get(s).unwrap())),
+            Operator::Terminal(TerminalType::Float(f)) => f,
+            Operator::Terminal(TerminalType::Inputf64(ref s)) => match inputs.get(s) {
+                Some(v) => *v,
+                None => return Err(EvalError::MissingInput(s.clone())),
+            },
+            Operator::Terminal(TerminalType::Arg(a)) => match args.get(a) {
+                Some(v) => *v,
+                None => return Err(EvalError::MissingInput(format!("Arg {}", a))),
+            },
+            Operator::Adf(k) => {
+                // Evaluate the arguments then the body of the
+                // function.  A function can only call functions
+                // defined before it so there is no recursion
+                let mut a:Vec<f64> = Vec::new();
+                for c in self.children() {
+                    a.push(c._evaluate(inputs, policy, adfs, args)?);
+                }
+                match adfs.get(k) {
+                    Some(f) => f._evaluate(inputs, policy, &adfs[..k], &a[..])?,
+                    None => return Err(EvalError::MissingChild(format!("{:?}", self.o))),
+                }
+            },
+            Operator::Defun(_) => panic!("Defun inside a branch"),
             Operator::If => {
-                let def = evaluate!(d);
-                let e:f64;
-                if def > 0.0 {
-                    e = evaluate!(l);
+                // Only the leg taken is evaluated
+                if evaluate!(d) > 0.0 {
+                    evaluate!(l)
                 }else{
-                    e = evaluate!(r);
+                    evaluate!(r)
                 }
-                Some(e)
             },
-            Operator::Lt => {
+            ref o => {
                 let left = evaluate!(l);
-                let right = evaluate!(r);
-                if left < right {
-                    Some(1.0)
-                }else{
-                    Some(-1.0)
+                let right = if o.unary() {0.0} else {evaluate!(r)};
+                return o.apply(left, right, policy);
+            },
+        };
+        if policy == Policy::Checked && !ret.is_finite() {
+            Err(EvalError::NonFinite(format!("{:?}", self.o)))
+        }else{
+            Ok(ret)
+        }
+    }
+
+    /// Evaluate a programme over many rows of data at once, a column
+    /// at a time.  `columns` are the input columns named by `names`
+    /// (see `Data::columns`) and `rows` the rows to evaluate.  Each
+    /// operator is applied to the values of its children for every
+    /// row in one pass.  The values are those of `evaluate`.  When
+    /// more than one row fails the error need not be for the first
+    #[allow(dead_code)]
+    pub fn evaluate_columns(&self, names:&[String], columns:&[Vec<f64>],
+                            rows:&[usize], policy:Policy)
+                            -> Result<Vec<f64>, EvalError> {
+        self.evaluate_columns_cached(names, columns, rows, policy, &mut Cache::new(0))
+    }
+
+    /// As `evaluate_columns`, but the values of subtrees are looked
+    /// up in and stored in `cache`
+    pub fn evaluate_columns_cached(&self, names:&[String], columns:&[Vec<f64>],
+                                   rows:&[usize], policy:Policy, cache:&mut Cache)
+                                   -> Result<Vec<f64>, EvalError> {
+        let (adfs, node) = self.branches();
+        let mut keys = HashMap::new();
+        if cache.enabled() {
+            node._cache_keys(&mut keys);
+        }
+        let ret = node._evaluate_columns(names, columns, rows, policy, &adfs[..], &[],
+                                         cache, &keys, true)?;
+        if ret.iter().all(|x| x.is_finite()) {
+            Ok(ret)
+        }else{
+            Err(EvalError::NonFinite(format!("{:?}", node.o)))
+        }
+    }
+
+    // The cache key of each subtree of this branch, by the address
+    // of its root.  A hash of the operator and the keys of the
+    // children, so the tree is walked once however deep it is.
+    // Subtrees that call ADFs have no key as their values depend on
+    // the programme.  Returns the key of this tree
+    fn _cache_keys(&self, keys:&mut HashMap<*const Node, u64>) -> Option<u64> {
+        let mut hasher = DefaultHasher::new();
+        format!("{:?}", self.o).hash(&mut hasher);
+        let mut keyed = match self.o {
+            Operator::Adf(_) => false,
+            _ => true,
+        };
+        for c in self.children() {
+            match c._cache_keys(keys) {
+                Some(k) => k.hash(&mut hasher),
+                None => keyed = false,
+            }
+        }
+        if !keyed {
+            return None;
+        }
+        let k = hasher.finish();
+        keys.insert(self as *const Node, k);
+        Some(k)
+    }
+
+    // `args` are the values of the arguments to the ADF being
+    // evaluated, for each row in `rows`.  `keys` are the cache keys
+    // from `_cache_keys`.  `full` is set if `rows` are all the rows
+    // being evaluated, not those taking one leg of an `If`
+    fn _evaluate_columns(&self, names:&[String], columns:&[Vec<f64>],
+                         rows:&[usize], policy:Policy,
+                         adfs:&[&Node], args:&[Vec<f64>],
+                         cache:&mut Cache, keys:&HashMap<*const Node, u64>,
+                         full:bool)
+                         -> Result<Vec<f64>, EvalError> {
+        macro_rules! evaluate {
+            ($a:ident, $rows:expr, $args:expr, $full:expr) => {
+                match self.$a {
+                    Some(ref $a) => $a._evaluate_columns(names, columns, $rows,
+                                                         policy, adfs, $args,
+                                                         cache, keys, $full)?,
+                    None => return Err(EvalError::MissingChild(format!("{:?}", self.o))),
                 }
+            }
+        }
+
+        // A node that is not evaluated for any row cannot fail
+        if rows.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // Only the values of subtrees of the result producing branch
+        // for all the rows are cached.  Not terminals, they are
+        // cheap, and not calls to ADFs as they depend on the
+        // programme
+        let key = match self.o {
+            Operator::Terminal(_) => None,
+            _ if cache.enabled() && full && args.is_empty() =>
+                keys.get(&(self as *const Node)).cloned(),
+            _ => None,
+        };
+        if let Some(k) = key {
+            if let Some(v) = cache.get(k) {
+                return Ok(v);
+            }
+        }
+
+        let ret:Vec<f64> = match self.o {
+            Operator::Terminal(TerminalType::Float(f)) => vec![f; rows.len()],
+            Operator::Terminal(TerminalType::Inputf64(ref s)) =>
+                match names.iter().position(|n| n == s) {
+                    Some(j) => rows.iter().map(|i| columns[j][*i]).collect(),
+                    None => return Err(EvalError::MissingInput(s.clone())),
+                },
+            Operator::Terminal(TerminalType::Arg(a)) => match args.get(a) {
+                Some(v) => v.clone(),
+                None => return Err(EvalError::MissingInput(format!("Arg {}", a))),
             },
-            Operator::Gt => {
-                let left = evaluate!(l);
-                let right = evaluate!(r);
-                if left > right {
-                    Some(1.0)
+            Operator::Adf(k) => {
+                let mut a:Vec<Vec<f64>> = Vec::new();
+                for c in self.children() {
+                    a.push(c._evaluate_columns(names, columns, rows,
+                                               policy, adfs, args, cache, keys, full)?);
+                }
+                match adfs.get(k) {
+                    Some(f) => f._evaluate_columns(names, columns, rows, policy,
+                                                   &adfs[..k], &a[..], cache, keys, full)?,
+                    None => return Err(EvalError::MissingChild(format!("{:?}", self.o))),
+                }
+            },
+            Operator::Defun(_) => panic!("Defun inside a branch"),
+            Operator::If => {
+                // Split the rows by the leg they take and evaluate
+                // each leg for its rows only
+                let condition = evaluate!(d, rows, args, full);
+                let (take_l, take_r):(Vec<usize>, Vec<usize>) =
+                    (0..rows.len()).partition(|p| condition[*p] > 0.0);
+                let subset = |ps:&[usize]| {
+                    let r:Vec<usize> = ps.iter().map(|p| rows[*p]).collect();
+                    let a:Vec<Vec<f64>> = args.iter().map(|v| {
+                        ps.iter().map(|p| v[*p]).collect()
+                    }).collect();
+                    (r, a)
+                };
+                let (rows_l, args_l) = subset(&take_l[..]);
+                let (rows_r, args_r) = subset(&take_r[..]);
+                let value_l = evaluate!(l, &rows_l[..], &args_l[..], false);
+                let value_r = evaluate!(r, &rows_r[..], &args_r[..], false);
+                let mut ret = vec![0.0; rows.len()];
+                for (p, v) in take_l.iter().zip(value_l) {
+                    ret[*p] = v;
+                }
+                for (p, v) in take_r.iter().zip(value_r) {
+                    ret[*p] = v;
+                }
+                ret
+            },
+            ref o => {
+                let left = evaluate!(l, rows, args, full);
+                if o.unary() {
+                    left.iter().map(|x| o.apply(*x, 0.0, policy)).collect::<Result<_, _>>()?
                 }else{
-                    Some(-1.0)
+                    let right = evaluate!(r, rows, args, full);
+                    left.iter().zip(right).map(|(x, y)| o.apply(*x, y, policy))
+                        .collect::<Result<_, _>>()?
                 }
             },
-            Operator::Add => {
-                let left = evaluate!(l);
-                let right = evaluate!(r);
-                Some(left+right)
+        };
+        if policy == Policy::Checked && !ret.iter().all(|x| x.is_finite()) {
+            return Err(EvalError::NonFinite(format!("{:?}", self.o)));
+        }
+        if let Some(k) = key {
+            cache.insert(k, &ret[..]);
+        }
+        Ok(ret)
+    }
+
+    /// Compile a programme to postfix instructions for a stack
+    /// machine.  `names` are the input columns of a row of data, in
+    /// order.  The compiled programme has the same value as
+    /// `evaluate` and fails the same way
+    pub fn compile(&self, names:&[String]) -> Compiled {
+        let (adfs, node) = self.branches();
+        let mut functions:Vec<Vec<Instruction>> = Vec::new();
+        for (k, f) in adfs.iter().enumerate() {
+            let mut code:Vec<Instruction> = Vec::new();
+            f._compile(names, k, &mut code);
+            functions.push(code);
+        }
+        let mut code:Vec<Instruction> = Vec::new();
+        node._compile(names, adfs.len(), &mut code);
+        Compiled::new(functions, code, names, &node.o)
+    }
+
+    // Append the instructions for this tree to `code`.  It can call
+    // the first `n_adfs` ADFs.  Whatever `_evaluate` would fail on
+    // becomes a `Fail` instruction where it would fail
+    fn _compile(&self, names:&[String], n_adfs:usize, code:&mut Vec<Instruction>) {
+        macro_rules! compile {
+            ($a:ident) => {
+                match self.$a {
+                    Some(ref $a) => $a._compile(names, n_adfs, code),
+                    None => code.push(Instruction::Fail(
+                        EvalError::MissingChild(format!("{:?}", self.o)))),
+                }
+            }
+        }
+        match self.o {
+            Operator::Terminal(TerminalType::Float(f)) => code.push(Instruction::Const(f)),
+            Operator::Terminal(TerminalType::Inputf64(ref s)) =>
+                code.push(match names.iter().position(|n| n == s) {
+                    Some(i) => Instruction::Input(i),
+                    None => Instruction::Fail(EvalError::MissingInput(s.clone())),
+                }),
+            Operator::Terminal(TerminalType::Arg(a)) => code.push(Instruction::Arg(a)),
+            Operator::Adf(k) => {
+                let c = self.children();
+                for a in c.iter() {
+                    a._compile(names, n_adfs, code);
+                }
+                code.push(if k < n_adfs {
+                    Instruction::Call(k, c.len())
+                }else{
+                    Instruction::Fail(EvalError::MissingChild(format!("{:?}", self.o)))
+                });
             },
-            Operator::Remainder => {
-                let left = evaluate!(l);
-                let right = evaluate!(r);
-                Some(left%right)
+            Operator::Defun(_) => panic!("Defun inside a branch"),
+            Operator::If => {
+                // Jump over the leg not taken.  The targets are
+                // filled in when they are known
+                compile!(d);
+                let branch = code.len();
+                code.push(Instruction::Branch(0));
+                compile!(l);
+                let jump = code.len();
+                code.push(Instruction::Jump(0));
+                code[branch] = Instruction::Branch(code.len());
+                compile!(r);
+                code[jump] = Instruction::Jump(code.len());
             },
-            Operator::Multiply => {
-                let left = evaluate!(l);
-                let right = evaluate!(r);
-                Some(left*right)
+            ref o => {
+                compile!(l);
+                if o.unary() {
+                    code.push(Instruction::Unary(o.clone()));
+                }else{
+                    compile!(r);
+                    code.push(Instruction::Binary(o.clone()));
+                }
             },
-            Operator::Negate => {
-                let left = evaluate!(l);
-                Some(-1.0*left)
+        }
+    }
+
+}// impl Node
+
+impl Operator {
+    // Operators with one argument, in `l`
+    fn unary(&self) -> bool {
+        match *self {
+            Operator::Log|Operator::Invert|Operator::Negate|
+            Operator::Sqrt|Operator::Exp|Operator::Sin|Operator::Cos|
+            Operator::Tanh|Operator::Abs => true,
+            _ => false,
+        }
+    }
+
+    /// The value of an arithmetic operator.  `right` is ignored for
+    /// unary operators.  Shared by the tree interpreter and compiled
+    /// trees so they agree exactly
+    pub fn apply(&self, left:f64, right:f64, policy:Policy) -> Result<f64, EvalError> {
+        let protected = policy == Policy::Protected;
+        let checked = policy == Policy::Checked;
+
+        // An argument outside the domain of the operator.  Only fails
+        // under the checked policy
+        macro_rules! domain {
+            ($t:expr) => {
+                if checked && $t {
+                    return Err(EvalError::Domain(format!("{:?}", self)));
+                }
+            }
+        }
+
+        let ret = match *self {
+            Operator::Lt => if left < right {1.0} else {-1.0},
+            Operator::Gt => if left > right {1.0} else {-1.0},
+            Operator::Add => left+right,
+            Operator::Remainder => {
+                if protected && right == 0.0 {
+                    1.0
+                }else{
+                    domain!(right == 0.0);
+                    left%right
+                }
             },
+            Operator::Multiply => left*right,
+            Operator::Negate => -1.0*left,
             Operator::Log => {
-                let left = evaluate!(l);
-                Some(left.ln())
+                if !protected {
+                    domain!(left <= 0.0);
+                    left.ln()
+                }else if left == 0.0 {
+                    0.0
+                }else{
+                    left.abs().ln()
+                }
             },
             Operator::Invert => {
-                let left = evaluate!(l);
-                // FIXME  Divide by 0.0???!
-                Some(1.0/left)
+                if protected && left == 0.0 {
+                    1.0
+                }else{
+                    domain!(left == 0.0);
+                    1.0/left
+                }
+            },
+            Operator::Subtract => left-right,
+            Operator::Divide => {
+                if protected && right == 0.0 {
+                    1.0
+                }else{
+                    domain!(right == 0.0);
+                    left/right
+                }
             },
+            Operator::Sqrt => {
+                if protected {
+                    left.abs().sqrt()
+                }else{
+                    domain!(left < 0.0);
+                    left.sqrt()
+                }
+            },
+            Operator::Exp => left.exp(),
+            Operator::Sin => left.sin(),
+            Operator::Cos => left.cos(),
+            Operator::Tanh => left.tanh(),
+            Operator::Abs => left.abs(),
+            Operator::Min => left.min(right),
+            Operator::Max => left.max(right),
+            Operator::Pow => {
+                if protected {
+                    let p = left.abs().powf(right);
+                    if p.is_finite() {p} else {1.0}
+                }else{
+                    let p = left.powf(right);
+                    domain!(p.is_nan());
+                    p
+                }
+            },
+            _ => panic!("Cannot apply {:?}", self),
         };
-        ret
+        if checked && !ret.is_finite() {
+            Err(EvalError::NonFinite(format!("{:?}", self)))
+        }else{
+            Ok(ret)
+        }
     }
+}
 
-    // Using known facts about operators reduce the size of a tree by
-    // eliminating redundant subtrees.
-    // pub fn reduce_tree(&mut o) -> Option<Operator> {
-    //     macro_rules! ev {
-    //     }
-    //     let ret = o {
-    //         // For floats return the number in a Some.  For inputs
-    //         // return None (cannot reduce what is not known)
-    //         Operator::Terminal(TerminalType::Float(f)) => Some(Operator::Terminal(TerminalType::Float(f))),
-    //         Operator::Terminal(TerminalType::Inputf64(ref s)) => None,
-
-    //         Operator::If => {
-    //             // For a `If` node check the condition.  If it is
-    //             // alays <0 replace the If node with the `r` leg, if
-    //             // it is always >0 replace with `l` leg.
-    //             match reduce_tree(&mut self.d) {
-    //                 Some(f) => {
-    //                     if f > 0.0 {
-    //                         Some(o.l)
-    //                     }else{
-    //                         Some(o.r)
-    //                     }
-    //                 },
-    //                 None => None,
-    //             }
-    //         },
-    //         Operator::Lt => {
-    //             let left = evaluate!(l);
-    //             let right = evaluate!(r);
-    //             if left < right {
-    //                 Some(1.0)
-    //             }else{
-    //                 Some(-1.0)
-    //             }
-    //         },
-    //         Operator::Gt => {
-    //             let left = evaluate!(l);
-    //             let right = evaluate!(r);
-    //             if left > right {
-    //                 Some(1.0)
-    //             }else{
-    //                 Some(-1.0)
-    //             }
-    //         },
-    //         Operator::Add => {
-    //             let left = evaluate!(l);
-    //             let right = evaluate!(r);
-    //             Some(left+right)
-    //         },
-    //         Operator::Remainder => {
-    //             let left = evaluate!(l);
-    //             let right = evaluate!(r);
-    //             Some(left%right)
-    //         },
-    //         Operator::Multiply => {
-    //             let left = evaluate!(l);
-    //             let right = evaluate!(r);
-    //             Some(left*right)
-    //         },
-    //         Operator::Negate => {
-    //             let left = evaluate!(l);
-    //             Some(-1.0*left)
-    //         },
-    //         Operator::Log => {
-    //             let left = evaluate!(l);
-    //             Some(left.ln())
-    //         },
-    //         Operator::Invert => {
-    //             let left = evaluate!(l);
-    //             // FIXME  Divide by 0.0???!
-    //             Some(1.0/left)
-    //         },
-    //     };
-    // }
-
-}// impl Node
@@ -0,0 +1,150 @@
+//! Multi-objective evolution after NSGA-II (Deb et al. 2002).  Each
+//! tree has several objectives, all minimised.  Trees are ranked by
+//! the Pareto front they are in and, within a front, by how crowded
+//! their neighbourhood is.  Used when `selection` is `pareto`
+
+use score::Score;
+use std::cmp::Ordering;
+use std::f64;
+
+/// The things a tree can be judged by
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Objective {
+    // How far from the true values the tree is
+    Error,
+
+    // The number of nodes
+    Size,
+    Depth,
+
+    // The number of distinct inputs used
+    Inputs,
+}
+
+impl Objective {
+    pub fn new_from_str(s:&str) -> Objective {
+        match s {
+            "error" => Objective::Error,
+            "size" => Objective::Size,
+            "depth" => Objective::Depth,
+            "inputs" => Objective::Inputs,
+            _ => panic!("Unknown objective: {}", s),
+        }
+    }
+
+    /// E.g: `error size depth`
+    pub fn objectives_from_str(s:&str) -> Vec<Objective> {
+        s.split_whitespace().map(|x| Objective::new_from_str(x)).collect()
+    }
+
+    pub fn value(&self, score:&Score) -> f64 {
+        match *self {
+            Objective::Error => score.error,
+            Objective::Size => score.size as f64,
+            Objective::Depth => score.depth as f64,
+            Objective::Inputs => score.inputs as f64,
+        }
+    }
+}
+
+/// `a` is no worse than `b` in every objective and better in one
+pub fn dominates(a:&[f64], b:&[f64]) -> bool {
+    let mut better = false;
+    for (x, y) in a.iter().zip(b) {
+        if x > y {
+            return false;
+        }
+        if x < y {
+            better = true;
+        }
+    }
+    better
+}
+
+/// Sort points into Pareto fronts.  The first front is the points
+/// nothing dominates, the second those only the first dominates, and
+/// so on.  Points are taken in lexicographic order so a point can
+/// only be dominated by one before it
+pub fn fronts(points:&[Vec<f64>]) -> Vec<Vec<usize>> {
+    let mut order:Vec<usize> = (0..points.len()).collect();
+    order.sort_by(|a, b| {
+        for (x, y) in points[*a].iter().zip(points[*b].iter()) {
+            match x.partial_cmp(y) {
+                Some(Ordering::Equal) | None => continue,
+                Some(o) => return o,
+            }
+        }
+        Ordering::Equal
+    });
+    let mut ret:Vec<Vec<usize>> = Vec::new();
+    for i in order {
+        let k = ret.iter().position(|f| {
+            !f.iter().any(|j| dominates(&points[*j][..], &points[i][..]))
+        });
+        match k {
+            Some(k) => ret[k].push(i),
+            None => ret.push(vec![i]),
+        }
+    }
+    ret
+}
+
+/// The crowding distance of each point in `front`.  The sum over the
+/// objectives of the distance between the neighbours on each side,
+/// scaled by the range of the objective.  Points at the ends are
+/// infinitely far from a crowd
+pub fn crowding(points:&[Vec<f64>], front:&[usize]) -> Vec<f64> {
+    let mut ret = vec![0.0; front.len()];
+    if front.is_empty() {
+        return ret;
+    }
+    for m in 0..points[front[0]].len() {
+        // Positions in `front` in order of this objective
+        let mut order:Vec<usize> = (0..front.len()).collect();
+        order.sort_by(|a, b| {
+            points[front[*a]][m].partial_cmp(&points[front[*b]][m]).unwrap_or(Ordering::Equal)
+        });
+        let lo = points[front[order[0]]][m];
+        let hi = points[front[order[order.len() - 1]]][m];
+        ret[order[0]] = f64::INFINITY;
+        ret[order[order.len() - 1]] = f64::INFINITY;
+        if hi > lo {
+            for k in 1..order.len().saturating_sub(1) {
+                let d = points[front[order[k + 1]]][m] - points[front[order[k - 1]]][m];
+                ret[order[k]] += d / (hi - lo);
+            }
+        }
+    }
+    ret
+}
+
+/// The front (zero is the best) and crowding distance of each point
+pub fn rank(points:&[Vec<f64>]) -> Vec<(usize, f64)> {
+    let mut ret = vec![(0, 0.0); points.len()];
+    for (r, f) in fronts(points).iter().enumerate() {
+        for (i, c) in f.iter().zip(crowding(points, &f[..])) {
+            ret[*i] = (r, c);
+        }
+    }
+    ret
+}
+
+/// The `n` points that survive.  Whole fronts are kept, best first.
+/// The last front that does not fit is thinned by keeping its least
+/// crowded points
+pub fn survivors(points:&[Vec<f64>], n:usize) -> Vec<usize> {
+    let mut ret = Vec::new();
+    for f in fronts(points) {
+        if ret.len() + f.len() <= n {
+            ret.extend(f);
+        }else{
+            let mut f:Vec<(usize, f64)> = f.iter().cloned().
+                zip(crowding(points, &f[..])).collect();
+            f.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+            let k = n - ret.len();
+            ret.extend(f.iter().take(k).map(|x| x.0));
+            break;
+        }
+    }
+    ret
+}
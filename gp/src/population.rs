@@ -12,41 +12,102 @@
 //! * simulate Run a individual over test data and utput the results
 //! to a file.
 
+//! * cross_validate Estimate the error on unseen data with k-fold
+//! cross validation and write a summary to a file.
+
+//! * classify Classify a case with the saved trees.  The
+//! `classify` action classifies the test data and writes the
+//! results to a file.
+
 //! * save_state restore_state Save or restore the population from
 //! disc. UNIMPLEMENTED.
 
 //! * report: Display the best classifiers for each class. UNIMPLEMENTED
 
 
+use compiled::Compiled;
 use config::Config;
+use data::Partition;
+use data::Stratify;
+use fitness::Fitness;
 use fs2::FileExt;
 use node::Node;
 use node::NodeBox;
+use node::Policy;
+use node::FunctionSet;
+use node::Primitives;
+use node::Branch;
+use node::TerminalType;
+use island::Topology;
+use pareto;
+use pareto::Objective;
 use rng;
+use rng::Stream;
+use rng::Streams;
 use score::Score;
+use std::cmp::Ordering;
 use std::collections::BTreeMap;    
 use std::collections::HashMap;    
+use std::collections::HashSet;
 use std::collections::hash_map::Entry::Vacant;
 use std::f64;
 use std::fs::File;
 use std::io::BufRead;
 use std::io::BufReader;
 use std::io::Write;
+use std::mem;
 use std::thread;
 use super::Data;
-use inputs::Inputs;
 use super::Recorder;
 use super::score_individual;
+use score::ScoreError;
+use score::score_for_class;
+use score::score_individual_cached;
+use cache::Cache;
+use selection::Crowded;
+use selection::Lexicase;
+use selection::Roulette;
+use selection::Scheme;
+use selection::Selection;
+use selection::Tournament;
+use tune;
 
 /// Define a individual.  Consists of a node, a id, and a score.
 #[derive(Clone)]
 struct Tree {
     // FIXME Should this be in node.rs?
     id:usize,
+
+    // On the training data.  Evolution only uses this
     score:Score,
+
+    // On the validation data, to choose the best tree, and on the
+    // test data, to report it.  `None` if there is no such data or
+    // the tree fails on it
+    validation:Option<Score>,
+    testing:Option<Score>,
+
     tree:NodeBox,
 } 
 
+impl Tree {
+    // A tree with its score on the training data.  It is scored on
+    // the validation and test data here, for the class it has on the
+    // training data if classifying
+    fn new(id:usize, score:Score, tree:NodeBox, d:&Data, policy:Policy,
+           fitness:Fitness) -> Tree {
+        Tree{
+            id:id,
+            validation:score_for_class(&tree, d, Partition::Validation, policy, fitness,
+                                       &score.class).ok(),
+            testing:score_for_class(&tree, d, Partition::Testing, policy, fitness,
+                                    &score.class).ok(),
+            score:score,
+            tree:tree,
+        }
+    }
+}
+
 #[derive(Clone)]
 /// A collection of [Trees](struct.Tree.html)
 pub struct Forest {
@@ -118,6 +179,15 @@ impl Forest {
         assert!(self._check_sz() == 0);
     }
 
+    // The trees in order of id.  Iterating `trees` directly is in a
+    // different order each run, so anything that depends on the order
+    // uses this to be the same for the same seed
+    fn by_id(&self) -> Vec<&Tree> {
+        let mut ret:Vec<&Tree> = self.trees.values().collect();
+        ret.sort_by(|a, b| a.id.cmp(&b.id));
+        ret
+    }
+
     // Check if a Tree is in this Forest by string
     fn has_tree_str(&self, t:&str) -> bool {
         self.trees.contains_key(t)
@@ -187,9 +257,228 @@ pub struct Population {
     // There is one Population
     
     pub handle:Option<thread::JoinHandle<()>>,
+
+    /// The island being evolved now.  Empty between generations
     forest:Forest,
+
+    /// The islands.  There is one unless configured otherwise
+    islands:Vec<Island>,
+
+    /// How individuals move between islands
+    migration:MigrationParams,
+
     config:Config,
     data:Data,
+
+    /// What new trees are built from
+    primitives:Primitives,
+
+    /// Simplify new trees (see `Node::rationalise`)
+    simplify:bool,
+
+    /// How operators are evaluated.  Recorded in the save file
+    policy:Policy,
+
+    /// What scoring measures (see fitness.rs)
+    fitness:Fitness,
+
+    /// Values of subtrees shared by the individuals of a generation.
+    /// One for each thread that scores new individuals
+    caches:Vec<Cache>,
+
+    /// How trees are mutated
+    mutation:MutationParams,
+
+    /// How the constants of the best trees are tuned
+    tune:TuneParams,
+
+    /// How parents are selected
+    selection:Scheme,
+
+    /// The best tree at the end of evolution
+    best:Option<Tree>,
+
+    /// Seeds the random number streams (see rng.rs)
+    seed:Vec<u32>,
+
+    /// What Pareto selection minimises
+    objectives:Vec<Objective>,
+
+    /// How big trees are penalised
+    parsimony:ParsimonyParams,
+
+    /// Added to the names of the files a run writes, so each fold
+    /// of a cross validation has its own.  Empty otherwise
+    run_suffix:String,
+}
+
+/// The ways a tree is mutated.  Recorded in the births and deaths
+/// file
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mutation {
+    // A child is replaced by a new random subtree.  If the node
+    // chosen is a terminal it is mutated as one of the kinds below
+    Subtree,
+
+    // The operator of a node is replaced with another with as many
+    // arguments
+    Point,
+
+    // The result producing branch is replaced with one of its
+    // subtrees
+    Hoist,
+
+    // A subtree is replaced with a terminal
+    Shrink,
+
+    // A constant has a normally distributed number added to it
+    Gaussian,
+
+    // A constant is multiplied by `e` to a normally distributed power
+    LogScale,
+
+    // An input is replaced by another with the same unit, or an ADF
+    // argument by another argument
+    SwapInput,
+
+    // A constant is replaced by an input
+    ToInput,
+
+    // An input or ADF argument is replaced by a constant
+    ToConstant,
+}
+
+/// How the constants of the best trees are tuned
+struct TuneParams {
+    /// Tune every this many generations.  Zero for never
+    generations:usize,
+
+    /// How many of the best trees are tuned
+    count:usize,
+
+    method:tune::Method,
+
+    /// How many times each tree can be scored as it is tuned
+    evaluations:usize,
+}
+
+/// A sub-population that evolves apart from the others
+struct Island {
+    forest:Forest,
+    params:GenerationParams,
+}
+
+/// The parameters of `_new_generation`.  Can be different for each
+/// island
+#[derive(Debug, Clone, Copy)]
+struct GenerationParams {
+    mutate_prob:usize,
+    copy_prob:usize,
+    crossover_percent:usize,
+    max_population:usize,
+}
+
+/// How individuals move between islands
+struct MigrationParams {
+    /// Migrate every this many generations.  Zero for never
+    interval:usize,
+
+    /// How many of the best of each island migrate
+    count:usize,
+
+    topology:Topology,
+}
+
+/// The pressure on the quality of a tree to be small
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Parsimony {
+    Off,
+
+    // The quality less the coefficient times the size
+    Linear(f64),
+
+    // The coefficient is set each generation to the covariance of
+    // size and quality over the variance of size, so selection does
+    // not make the mean size grow (Poli and McPhee, 2008)
+    Covariant,
+}
+
+/// How big trees are penalised.  The size is the number of nodes or
+/// the depth
+struct ParsimonyParams {
+    pressure:Parsimony,
+    depth:bool,
+
+    /// Crossover does not make trees bigger than these
+    max_size:Option<usize>,
+    max_depth:Option<usize>,
+}
+
+impl ParsimonyParams {
+    fn size(&self, score:&Score) -> f64 {
+        if self.depth {score.depth as f64} else {score.size as f64}
+    }
+
+    // Within the hard limits
+    fn fits(&self, n:&NodeBox) -> bool {
+        self.max_size.map_or(true, |m| n.count_nodes() <= m) &&
+            self.max_depth.map_or(true, |m| n.depth() <= m)
+    }
+}
+
+/// How trees are mutated
+struct MutationParams {
+    /// The kinds of mutation (`Subtree`, `Point`, `Hoist` and
+    /// `Shrink`) and their weights
+    kinds:Vec<(Mutation, f64)>,
+
+    /// The standard deviation of the number added to a constant
+    constant_step:f64,
+
+    /// The standard deviation of the log of the factor a constant is
+    /// multiplied by
+    constant_log_step:f64,
+}
+
+impl MutationParams {
+    // The kinds of mutation from a string of `<kind>:<weight>`
+    // separated by white space.  If there is no weight it is one
+    fn kinds_from_str(s:&str) -> Vec<(Mutation, f64)> {
+        let mut ret = Vec::new();
+        for c in s.split_whitespace() {
+            let mut iter = c.split(':');
+            let k = match iter.next().unwrap() {
+                "Subtree" => Mutation::Subtree,
+                "Point" => Mutation::Point,
+                "Hoist" => Mutation::Hoist,
+                "Shrink" => Mutation::Shrink,
+                x => panic!("Config: mutations.  Unknown mutation {}", x),
+            };
+            let w = match iter.next() {
+                Some(w) => w.parse::<f64>().
+                    expect(format!("Config: mutations.  Invalid weight {}", c).as_str()),
+                None => 1.0,
+            };
+            ret.push((k, w));
+        }
+        ret
+    }
+
+    // Choose a kind of mutation with probability in proportion to
+    // its weight
+    fn choose(&self, rng:&mut Stream) -> Mutation {
+        let total:f64 = self.kinds.iter().map(|k| k.1).sum();
+        if total > 0.0 {
+            let mut x = rng.gen_range(0.0, total);
+            for k in self.kinds.iter() {
+                if x < k.1 {
+                    return k.0;
+                }
+                x -= k.1;
+            }
+        }
+        Mutation::Subtree
+    }
 }
 
 impl Population {
@@ -211,15 +500,237 @@ impl Population {
                     expect("Config: data_file").as_str()
             );
 
-        let data = Data::new(&data_file,
+        // The seed is words separated by white space.  E.g: `seed 2
+        // 3 8 1 209`.  If there is none the run cannot be repeated
+        let seed = match config.get_string("seed") {
+            Some(s) => s.split_whitespace().map(|x| {
+                x.parse::<u32>().expect("Config: seed")
+            }).collect(),
+            None => {
+                let seed = rng::entropy_seed();
+                eprintln!("Seed: {:?}", seed);
+                seed
+            },
+        };
+
+        // Data is partitioned with its own stream so changes in how
+        // the rest of the run uses random numbers do not change it
+        let mut data = Data::new(&data_file,
                              config.get_usize("training_percent").
-                             expect("Config: training_percent"));
+                             expect("Config: training_percent"),
+                             // Optional.  E.g: `validation_percent 20`
+                             match config.get_string("validation_percent") {
+                                 Some(s) => s.parse::<usize>().expect("Config: validation_percent"),
+                                 None => 0,
+                             },
+                             // Optional.  E.g: `stratify objective:5`
+                             match config.get_string("stratify") {
+                                 Some(s) => Stratify::new_from_str(s.as_str()),
+                                 None => Stratify::None,
+                             },
+                             &mut Stream::new(&seed[..], "partition"));
+
+        // Optional.  The partition can be given instead.  E.g:
+        // `partition_column Set` or `partition_file Set.txt`
+        if let Some(c) = config.get_string("partition_column") {
+            data.partition_from_column(c.as_str());
+        }
+        if let Some(f) = config.get_string("partition_file") {
+            let f = Population::_local_file_name_glbl(config, f.as_str());
+            data.read_partition(f.as_str()).
+                expect(format!("Could not read: {}", f).as_str());
+        }
+
+        // Optional.  E.g: `classification true`.  Each value of the
+        // objective is a class
+        let classification = config.get_string("classification") == Some("true".to_string());
+        if classification {
+            data.set_classes();
+        }
+
+        // The arity of each automatically defined function.
+        // Optional.  E.g: `adfs 2 1` for two ADFs, the first with two
+        // arguments and the second with one
+        let adfs:Vec<usize> = match config.get_string("adfs") {
+            Some(s) => s.split_whitespace().map(|x| {
+                let a = x.parse::<usize>().expect("Config: adfs");
+                if a < 1 || a > 3 {
+                    panic!("Config: adfs.  Arity must be 1, 2, or 3 not {}", a);
+                }
+                a
+            }).collect(),
+            None => Vec::new(),
+        };
+        // The units of the inputs and objective, from the data file
+        let mut units = HashMap::new();
+        for i in 0..data.input_names.len() {
+            if let Some(ref u) = data.units[i] {
+                units.insert(data.input_names[i].clone(), u.clone());
+            }
+        }
+        let primitives = Primitives{
+            names:data.input_names.clone(),
+            adfs:adfs,
+            units:units,
+            objective:match data.units.last() {
+                Some(u) => u.clone(),
+                None => None,
+            },
+
+            // Optional.  E.g: `functions Add:2 Multiply:2 If Input:8`
+            functions:match config.get_string("functions") {
+                Some(s) => FunctionSet::new_from_str(s.as_str()),
+                None => FunctionSet::new(),
+            },
+        };
         
         Population {
+            best:None,
+            seed:seed,
+            run_suffix:String::new(),
             forest:Forest::new(),
+            islands:Vec::new(),
+
+            // Optional.  E.g: `migration_interval 10`,
+            // `migration_count 5` and `migration_topology ring`
+            migration:MigrationParams{
+                interval:match config.get_string("migration_interval") {
+                    Some(s) => s.parse::<usize>().expect("Config: migration_interval"),
+                    None => 0,
+                },
+                count:match config.get_string("migration_count") {
+                    Some(s) => s.parse::<usize>().expect("Config: migration_count"),
+                    None => 1,
+                },
+                topology:match config.get_string("migration_topology") {
+                    Some(s) => Topology::new_from_str(s.as_str()),
+                    None => Topology::Ring,
+                },
+            },
             handle:None,
             data:data, 
             config:config.clone(),
+            primitives:primitives,
+            simplify:config.get_string("simplify") == Some("true".to_string()),
+            policy:match config.get_string("policy") {
+                Some(s) => Policy::new_from_str(s.as_str()),
+                None => Policy::Strict,
+            },
+
+            // Optional.  E.g: `fitness hinge`
+            fitness:match config.get_string("fitness") {
+                Some(s) => Fitness::new_from_str(s.as_str()),
+                None if classification => Fitness::Hinge,
+                None => Fitness::Rmse,
+            },
+
+            // Optional.  Megabytes for the subtree cache, shared
+            // between `threads` threads.  E.g: `threads 4`
+            caches:{
+                let threads = match config.get_string("threads") {
+                    Some(s) => s.parse::<usize>().expect("Config: threads"),
+                    None => 1,
+                };
+                if threads < 1 {
+                    panic!("Config: threads.  There must be at least one thread");
+                }
+                let budget = match config.get_string("cache_size") {
+                    Some(s) => s.parse::<usize>().expect("Config: cache_size") * 1024 * 1024,
+                    None => 0,
+                };
+                (0..threads).map(|_| Cache::new(budget / threads)).collect()
+            },
+
+            // Optional.  How far constants are moved by mutation
+            mutation:MutationParams{
+                // E.g: `mutations Subtree:4 Point:2 Hoist Shrink`
+                kinds:MutationParams::kinds_from_str(
+                    match config.get_string("mutations") {
+                        Some(ref s) => s.as_str(),
+                        None => "Subtree:1",
+                    }),
+                constant_step:match config.get_string("constant_step") {
+                    Some(s) => s.parse::<f64>().expect("Config: constant_step"),
+                    None => 1.0,
+                },
+                constant_log_step:match config.get_string("constant_log_step") {
+                    Some(s) => s.parse::<f64>().expect("Config: constant_log_step"),
+                    None => 0.5,
+                },
+            },
+
+            // Optional.  E.g: `selection tournament` and
+            // `tournament_size 7`.  The default is the roulette wheel
+            selection:match config.get_string("selection") {
+                Some(s) => Scheme::new_from_str(
+                    s.as_str(),
+                    match config.get_string("tournament_size") {
+                        Some(s) => s.parse::<usize>().expect("Config: tournament_size"),
+                        None => 7,
+                    }),
+                None => Scheme::Roulette,
+            },
+
+            // Optional.  E.g: `parsimony linear`,
+            // `parsimony_coefficient 0.001`, `parsimony_measure depth`,
+            // `max_size 100` and `max_depth 12`
+            parsimony:ParsimonyParams{
+                pressure:match config.get_string("parsimony") {
+                    Some(s) => match s.as_str() {
+                        "off" => Parsimony::Off,
+                        "linear" => Parsimony::Linear(
+                            match config.get_string("parsimony_coefficient") {
+                                Some(s) => s.parse::<f64>().expect("Config: parsimony_coefficient"),
+                                None => 0.001,
+                            }),
+                        "covariant" => Parsimony::Covariant,
+                        x => panic!("Config: parsimony.  Unknown parsimony {}", x),
+                    },
+                    None => Parsimony::Off,
+                },
+                depth:match config.get_string("parsimony_measure") {
+                    Some(s) => match s.as_str() {
+                        "size" => false,
+                        "depth" => true,
+                        x => panic!("Config: parsimony_measure.  Unknown measure {}", x),
+                    },
+                    None => false,
+                },
+                max_size:config.get_string("max_size").map(|s| {
+                    s.parse::<usize>().expect("Config: max_size")
+                }),
+                max_depth:config.get_string("max_depth").map(|s| {
+                    s.parse::<usize>().expect("Config: max_depth")
+                }),
+            },
+
+            // Optional.  E.g: `objectives error size depth`.  Only
+            // used by Pareto selection
+            objectives:Objective::objectives_from_str(
+                match config.get_string("objectives") {
+                    Some(ref s) => s.as_str(),
+                    None => "error size",
+                }),
+
+            // Optional.  Tuning the constants of the best trees
+            tune:TuneParams{
+                generations:match config.get_string("tune_generations") {
+                    Some(s) => s.parse::<usize>().expect("Config: tune_generations"),
+                    None => 0,
+                },
+                count:match config.get_string("tune_count") {
+                    Some(s) => s.parse::<usize>().expect("Config: tune_count"),
+                    None => 5,
+                },
+                method:match config.get_string("tune_method") {
+                    Some(s) => tune::Method::new_from_str(s.as_str()),
+                    None => tune::Method::NelderMead,
+                },
+                evaluations:match config.get_string("tune_evaluations") {
+                    Some(s) => s.parse::<usize>().expect("Config: tune_evaluations"),
+                    None => 100,
+                },
+            },
         }
     }
 
@@ -228,6 +739,8 @@ impl Population {
         match self.config.get_string("action").unwrap().as_str() {
             "evolve" => self._evolve(),
             "simulate" => self.simulate(),
+            "cross_validate" => self.cross_validate(),
+            "classify" => self.classify_test(),
             _ => Err("Unknown action!!".to_string()),
         }
     }
@@ -242,38 +755,59 @@ impl Population {
         let crossover_percent = self.config.get_usize("crossover_percent").unwrap();
 
         let max_population = self.config.get_usize("max_population").unwrap();
-        // The random number generator I use has its seed as a vector
-        // of usize.  Why?  Just use one number...
-        let seed:Vec<u32> = vec![self.config.get_u32("seed").unwrap()];
         let num_generations = self.config.get_usize("num_generations").unwrap();
         let bnd_fname = self._bnd_file_name();
         let mut bnd_rec = Recorder::new(bnd_fname.as_str());
-        let  generations_file = format!("{}/Data/{}/{}",
-                                        self.config.get_string("root_dir").expect("Config: root_dir"),
-                                        self.config.get_string("name").expect("Config: name"),
-                                        self.config.get_string("generations_file").unwrap());
+        let  generations_file = self._run_file_name(
+            self.config.get_string("generations_file").unwrap().as_str());
         let mut generation_recorder = Recorder::new(&generations_file[..]);
         let save_file = self._save_file_name();
         
         // Write the header for the generation file
-        let s = "elapsed, generation".to_string();
+        let s = "elapsed, generation, island, population, best, mean".to_string();
         generation_recorder.write_line(&s[..]);
         generation_recorder.buffer.flush().unwrap();
 
-        rng::reseed(seed.as_slice());
+        let mut streams = Streams::new(&self.seed[..]);
 
         let mut generation = 0;
 
+        // Optional.  E.g: `islands 4`.  Each island's parameters can
+        // be set with `island_mutate_prob 1 2 5 10` and so on
+        let n_islands = self._n_islands();
+        self.islands = (0..n_islands).map(|i| Island{
+            forest:Forest::new(),
+            params:GenerationParams{
+                mutate_prob:self._island_param("mutate_prob", i, n_islands, mutate_prob),
+                copy_prob:self._island_param("copy_prob", i, n_islands, copy_prob),
+                crossover_percent:self._island_param("crossover_percent", i, n_islands,
+                                                     crossover_percent),
+                max_population:self._island_param("max_population", i, n_islands,
+                                                  max_population),
+            },
+        }).collect();
+
+        // The partition is saved with the trees so a reloaded run
+        // scores them on the same rows
+        let partition_file = format!("{}.partition", save_file);
         if self.config.get_string("reload").unwrap() == "true" {
             // Restore state from the last run
+            if let Err(e) = self.data.read_partition(partition_file.as_str()) {
+                eprintln!("Could not use saved partition: {}: {}", partition_file, e);
+            }
             self.restore_state().unwrap();
         }else{
-            // Initialise a random population
-
-            if self.forest.trees.len() == 0 {
-                self._initialise_rand(&mut bnd_rec, max_population);
-            }else{
-                eprintln!("population Not calling _initialise_rand");
+            self.data.write_partition(partition_file.as_str()).
+                expect(format!("Could not write: {}", partition_file).as_str());
+            // Initialise a random population on each island.  Ids
+            // are unique over all islands
+            for i in 0..n_islands {
+                mem::swap(&mut self.forest, &mut self.islands[i].forest);
+                self.forest.maxid = self._maxid();
+                let p = self.islands[i].params;
+                self._initialise_rand(&mut bnd_rec, p.max_population,
+                                      &mut streams.initialise);
+                mem::swap(&mut self.forest, &mut self.islands[i].forest);
             }
         }
 
@@ -289,30 +823,127 @@ impl Population {
             // FIXME Are there other criterion for ending a
             // simulation?
             
-            // Advance simulation by generating a new forest
-            self.forest = self._new_generation(
-                                          mutate_prob, copy_prob,
-                                          crossover_percent,
-                                          max_population,
-                                          &mut bnd_rec,
-                                          save_file.as_str());
-
-            // Write a report of this old generation
-            // Not saying much yet....
-
-            let s = format!("{}",// {}, {}, {}",
-                            generation,
-                            // id,
-                            // score,
-                            // tree.tree.to_string()
-            );
-            generation_recorder.write_line(&s[..]);
+            // Advance simulation by generating a new forest on each
+            // island
+            for i in 0..self.islands.len() {
+                mem::swap(&mut self.forest, &mut self.islands[i].forest);
+                self.forest.maxid = self._maxid();
+                let p = self.islands[i].params;
+                self.forest = self._new_generation(
+                    p.mutate_prob, p.copy_prob,
+                    p.crossover_percent,
+                    p.max_population,
+                    &mut bnd_rec,
+                    &mut streams.variation);
+
+                if self.tune.generations > 0 && generation % self.tune.generations == 0 {
+                    self._tune(&mut bnd_rec);
+                }
+                mem::swap(&mut self.forest, &mut self.islands[i].forest);
+
+                // Write a report of this island's generation
+                let forest = &self.islands[i].forest;
+                let best = match forest.score_trees.keys().next() {
+                    Some(s) => s.quality(),
+                    None => f64::NAN,
+                };
+                let mean = forest.by_id().iter().map(|t| t.score.quality()).sum::<f64>() /
+                    forest.trees.len() as f64;
+                let s = format!("{}, {}, {}, {}, {}",
+                                generation, i, forest.trees.len(), best, mean);
+                generation_recorder.write_line(&s[..]);
+            }
             generation_recorder.buffer.flush().unwrap(); 
+
+            if self.migration.interval > 0 && generation % self.migration.interval == 0 {
+                Population::_migrate(&mut self.islands, &self.migration, &mut bnd_rec,
+                                     &mut streams.variation);
+                bnd_rec.buffer.flush().unwrap();
+            }
+
+            let objectives = if self.selection == Scheme::Pareto {
+                &self.objectives[..]
+            }else{
+                &[]
+            };
+            Population::_save_trees(&self.islands, self.policy, objectives,
+                                    save_file.as_str());
         }
 
+        // The model is chosen on the validation data.  Its score on
+        // the test data is what is reported
+        self.best = self._choose_best();
         Ok(true)
     }
 
+    // How many islands.  At least one
+    fn _n_islands(&self) -> usize {
+        let n = match self.config.get_string("islands") {
+            Some(s) => s.parse::<usize>().expect("Config: islands"),
+            None => 1,
+        };
+        if n < 1 {
+            panic!("Config: islands.  There must be at least one island");
+        }
+        n
+    }
+
+    // A parameter of `_new_generation` for island `i`.  From the
+    // list in `island_<key>` if there is one, else `default`
+    fn _island_param(&self, key:&str, i:usize, n:usize, default:usize) -> usize {
+        let k = format!("island_{}", key);
+        match self.config.get_string(k.as_str()) {
+            Some(s) => {
+                let v:Vec<usize> = s.split_whitespace().map(|x| {
+                    x.parse::<usize>().expect(format!("Config: {}", k).as_str())
+                }).collect();
+                if v.len() != n {
+                    panic!("Config: {}.  Need a value for each of {} islands", k, n);
+                }
+                v[i]
+            },
+            None => default,
+        }
+    }
+
+    // The largest id on any island
+    fn _maxid(&self) -> usize {
+        self.islands.iter().map(|i| i.forest.maxid).
+            fold(self.forest.maxid, |a, b| if a > b {a} else {b})
+    }
+
+    // The best `count` trees of each island are copied to the islands
+    // the topology says.  Islands are then brought back to their size
+    // by deleting their worst trees
+    fn _migrate(islands:&mut Vec<Island>, params:&MigrationParams,
+                bnd_rec:&mut Recorder, rng:&mut Stream) {
+        let n = islands.len();
+        let mut migrants:Vec<(usize, usize, Tree)> = Vec::new();
+        for i in 0..n {
+            let forest = &islands[i].forest;
+            let best:Vec<Tree> = forest.score_trees.iter().
+                flat_map(|(_, v)| v.iter()).take(params.count).
+                map(|s| forest.trees.get(s).unwrap().clone()).collect();
+            for j in params.topology.destinations(i, n, rng) {
+                for t in best.iter() {
+                    migrants.push((i, j, t.clone()));
+                }
+            }
+        }
+        for (i, j, t) in migrants {
+            if islands[j].forest.has_tree_nb(&t.tree) {
+                continue;
+            }
+            bnd_rec.write_line(&format!("Migrate {} {} --> {}", t.id, i, j));
+            islands[j].forest.insert(t);
+        }
+        for island in islands.iter_mut() {
+            while island.forest.trees.len() > island.params.max_population {
+                Population::_delete_worst(&mut island.forest, bnd_rec);
+            }
+        }
+    }
+
     pub fn simulate(&self) -> Result<bool, String>{
         let out_file_name =
             self._local_file_name(
@@ -346,15 +977,234 @@ impl Population {
     }
 
 
+    /// k-fold cross validation.  The rows are dealt into `folds`
+    /// parts and each part in turn is the test data, with the rest
+    /// for training.  If there is a `simulate_tree` it is scored on
+    /// each part.  Else a run is evolved for each and its best tree
+    /// scored.  The fitness metric on each part and their mean and standard
+    /// deviation are written to `cross_validation_file`
+    pub fn cross_validate(&mut self) -> Result<bool, String> {
+        // Optional.  E.g: `folds 10`
+        let k = match self.config.get_string("folds") {
+            Some(s) => s.parse::<usize>().expect("Config: folds"),
+            None => 5,
+        };
+        if k < 2 || k > self.data.data.len() {
+            panic!("Config: folds.  Need from 2 to {} folds not {}",
+                   self.data.data.len(), k);
+        }
+        let tree = self.config.get_string("simulate_tree").
+            map(|s| NodeBox::new(Node::new_from_str(s.as_str())));
+        if tree.is_none() && self.config.get_string("reload").unwrap() == "true" {
+            // The reloaded trees would have seen the test data
+            return Err("Cannot reload when cross validating".to_string());
+        }
+
+        let folds = self.data.folds(k, &mut Stream::new(&self.seed[..], "folds"));
+        let mut errors:Vec<f64> = Vec::new();
+        for i in 0..k {
+            self.data.set_fold(&folds[..], i);
+            let score = match tree {
+                Some(ref t) => score_individual(t, &self.data,
+                                                Partition::Testing, self.policy,
+                                                self.fitness).ok(),
+                None => {
+                    // Each fold saves its trees in its own files.
+                    // Not over those of a run on all the data
+                    self.run_suffix = format!("_fold{}", i);
+                    let evolved = self._evolve();
+                    self.run_suffix = String::new();
+                    evolved?;
+                    match self.best {
+                        Some(ref t) => t.testing.clone(),
+                        None => None,
+                    }
+                },
+            };
+            // A tree that fails on the test data has no metric
+            let error = match score {
+                Some(s) => s.metric,
+                None => f64::NAN,
+            };
+            eprintln!("Fold {} {} {}", i, self.fitness.to_str(), error);
+            errors.push(error);
+        }
+
+        let file_name = self._local_file_name(
+            match self.config.get_string("cross_validation_file") {
+                Some(ref s) => s.as_str(),
+                None => "CrossValidation.txt",
+            });
+        let mut file_out = File::create(file_name.as_str()).unwrap();
+        file_out.lock_exclusive().
+            expect("Failed to lock cross validation file");
+        let mut lines = format!("fold, size, {}\n", self.fitness.to_str());
+        for i in 0..k {
+            lines += format!("{}, {}, {}\n", i, folds[i].len(), errors[i]).as_str();
+        }
+        let (mean, sd, n) = Population::_mean_sd(&errors[..]);
+        lines += format!("mean, {}, {}\n", n, mean).as_str();
+        lines += format!("sd, {}, {}\n", n, sd).as_str();
+        file_out.write_all(lines.as_bytes()).unwrap();
+        Ok(true)
+    }
+
+    // The mean and sample standard deviation of the finite values in
+    // `x`, and how many there are
+    fn _mean_sd(x:&[f64]) -> (f64, f64, usize) {
+        let x:Vec<f64> = x.iter().cloned().filter(|v| v.is_finite()).collect();
+        let n = x.len();
+        let mean = x.iter().sum::<f64>() / n as f64;
+        let ss = x.iter().map(|v| (v - mean).powi(2)).sum::<f64>();
+        let sd = if n > 1 {
+            (ss / (n - 1) as f64).sqrt()
+        }else{
+            f64::NAN
+        };
+        (mean, sd, n)
+    }
+
+    /// Classify the test data with the saved trees.  A line for each
+    /// case is written to `<name>_Classes.txt`:
+    /// `Class: "<class>"  Classification: "<class>" <score> ...`.  See
+    /// PostHoc/Classifiers.R
+    pub fn classify_test(&mut self) -> Result<bool, String> {
+        if self.data.classes.is_empty() {
+            return Err("Classify needs `classification true`".to_string());
+        }
+        let n_islands = self._n_islands();
+        self.islands = (0..n_islands).map(|_| Island{
+            forest:Forest::new(),
+            params:GenerationParams{mutate_prob:0, copy_prob:0,
+                                    crossover_percent:0, max_population:0},
+        }).collect();
+
+        // The test data is what the trees were not trained on
+        let save_file = self._save_file_name();
+        let partition_file = format!("{}.partition", save_file);
+        if let Err(e) = self.data.read_partition(partition_file.as_str()) {
+            eprintln!("Could not use saved partition: {}: {}", partition_file, e);
+        }
+        if let Err(e) = self.restore_state() {
+            return Err(format!("Cannot restore {}: {}", save_file, e));
+        }
+
+        let file_name = self._local_file_name(
+            format!("{}_Classes.txt",
+                    self.config.get_string("name").expect("Config: name")).as_str());
+        File::create(file_name.as_str()).unwrap();
+        let mut rec = Recorder::new(file_name.as_str());
+        let classifiers = self._classifiers();
+        let mut correct = 0;
+        for i in self.data.testing_i.iter() {
+            let row = &self.data.data[*i];
+            let class = Data::class_name(*row.last().unwrap());
+            if let Some((c, s)) = self._classify(&classifiers[..], row) {
+                if c == class {
+                    correct += 1;
+                }
+                rec.write_line(&format!("Class: \"{}\"  Classification: {}", class, s));
+            }
+        }
+        rec.buffer.flush().unwrap();
+        eprintln!("Classified {} of {} correctly", correct, self.data.testing_i.len());
+        Ok(true)
+    }
+
+    /// Classify a case, a row of inputs, with every tree.  The score
+    /// of a class is the sum of each of its trees' output times the
+    /// tree's quality, divided by the sum of the qualities.  A class
+    /// with no trees scores zero.  Returns the class with the highest
+    /// score and every class with its score, best first:
+    /// `"<class>" <score> "<class>" <score> ...`.  `None` if not
+    /// classifying or there are no trees
+    #[allow(dead_code)]
+    pub fn classify(&self, case:&[f64]) -> Option<(String, String)> {
+        self._classify(&self._classifiers()[..], case)
+    }
+
+    // Every tree on any island with its class and quality.  In order
+    // of id on each island so the sums in `_classify` are the same
+    // each run
+    fn _classifiers(&self) -> Vec<(String, f64, Compiled)> {
+        self.islands.iter().flat_map(|i| i.forest.by_id()).
+            filter_map(|t| t.score.class.as_ref().map(|c| {
+                (c.clone(), t.score.quality(), t.tree.compile(&self.data.input_names))
+            })).collect()
+    }
+
+    fn _classify(&self, classifiers:&[(String, f64, Compiled)],
+                 case:&[f64]) -> Option<(String, String)> {
+        if classifiers.is_empty() || self.data.classes.is_empty() {
+            return None;
+        }
+        let mut scores:Vec<(String, f64)> = Vec::new();
+        for c in self.data.classes.iter() {
+            let name = Data::class_name(*c);
+            // The quality weighted mean of the outputs
+            let (sum, q) = classifiers.iter().filter(|x| x.0 == name).
+                filter_map(|x| x.2.evaluate(case, self.policy).ok().map(|e| (e * x.1, x.1))).
+                filter(|e| e.0.is_finite()).
+                fold((0.0, 0.0), |a, e| (a.0 + e.0, a.1 + e.1));
+            scores.push((name, if q > 0.0 {sum / q} else {0.0}));
+        }
+        scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+        let all:Vec<String> = scores.iter().map(|s| format!("\"{}\" {}", s.0, s.1)).collect();
+        Some((scores[0].0.clone(), all.join(" ")))
+    }
+
+    /// The best tree of the run, chosen on the validation data, and
+    /// its scores
     pub fn report(&self) -> String {
-        "".to_string()
+        match self.best {
+            Some(ref t) => format!("Best {} Training: {} Validation: {} Test: {} Node: {}",
+                                   t.id, t.score.quality(),
+                                   Population::_quality(&t.validation),
+                                   Population::_quality(&t.testing),
+                                   t.tree.to_string()),
+            None => "".to_string(),
+        }
+    }
+
+    // A score that may be missing, for output
+    fn _quality(score:&Option<Score>) -> String {
+        match *score {
+            Some(ref s) => s.quality().to_string(),
+            None => "-".to_string(),
+        }
+    }
+
+    // The best tree on any island by its score on the validation
+    // data.  By its score on the training data if there is no
+    // validation data.  A tree that fails on the validation data
+    // cannot be the best
+    fn _choose_best(&self) -> Option<Tree> {
+        let no_validation = self.data.validation_i.is_empty();
+        let mut trees:Vec<&Tree> = self.islands.iter().
+            flat_map(|i| i.forest.trees.values()).collect();
+        trees.sort_by(|a, b| a.id.cmp(&b.id));
+        let mut best:Option<(&Tree, f64)> = None;
+        for t in trees {
+            let q = if no_validation {
+                t.score.quality()
+            }else{
+                match t.validation {
+                    Some(ref s) => s.quality(),
+                    None => continue,
+                }
+            };
+            if best.map_or(true, |b| q > b.1) {
+                best = Some((t, q));
+            }
+        }
+        best.map(|b| b.0.clone())
     }
 
     fn _simulate(&self) -> Result<Vec<Vec<f64>>, String> {
 
         let tree_string = self.config.get_string("simulate_tree").unwrap();
         let n = Box::new(Node::new_from_str(tree_string.as_str()));
-        let mut inputs = Inputs::new();
+        let code = n.compile(&self.data.input_names);
         let mut ret:Vec<Vec<f64>> = Vec::new();
         
         for d in self.data.data.iter() {
@@ -364,17 +1214,14 @@ impl Population {
 
             // Line for this input.
             let mut line:Vec<f64> = Vec::new();
-            // Prepare the input...
+            // Copy the input...
             for j in 0..self.data.input_names.len() {
-                let v:f64 = (*d)[j];
-                let h = self.data.input_names[j].clone();
-                inputs.insert(h.as_str(), v);
-                line.push(v);
+                line.push((*d)[j]);
             }
             // Put the actual value.  FIXME This should be a option as
             // not all data files wil have the solution, in the end.
             line.push(*d.last().unwrap());
-            if let Some(e) =  n.evaluate(&inputs) {
+            if let Ok(e) =  code.evaluate(d, self.policy) {
                 line.push(e);
             }
             ret.push(line);
@@ -393,15 +1240,22 @@ impl Population {
     fn _local_file_name(&self, f:&str) -> String {
         Population::_local_file_name_glbl(&self.config, f)
     }
+    /// A file name local to the simulation for a file a run writes.
+    /// `run_suffix` goes before the extension
+    fn _run_file_name(&self, f:&str) -> String {
+        let f = match f.rfind('.') {
+            Some(i) => format!("{}{}{}", &f[..i], self.run_suffix, &f[i..]),
+            None => format!("{}{}", f, self.run_suffix),
+        };
+        self._local_file_name(f.as_str())
+    }
     fn _save_file_name(&self) -> String {
-        self._local_file_name(self.config.
-                              get_string("save_file").unwrap().as_str())
+        self._run_file_name(self.config.
+                            get_string("save_file").unwrap().as_str())
     }
     fn _bnd_file_name(&self) -> String {
-        format!("{}/Data/{}/{}",
-                self.config.get_string("root_dir").expect("Config: root_dir"),
-                self.config.get_string("name").expect("Config: name"),
-                self.config.get_string("birthsanddeaths_filename").unwrap())
+        self._run_file_name(self.config.
+                            get_string("birthsanddeaths_filename").unwrap().as_str())
     }
 
     /// Restore state from a save file
@@ -416,10 +1270,45 @@ impl Population {
         let lines = buf_reader.lines();
         self.forest.clear();
 
+        // Trees before any `Island:` line are on the first island.
+        // The island being restored is in `self.forest`
+        let mut island = 0;
+        mem::swap(&mut self.forest, &mut self.islands[island].forest);
+
         for line in lines  {
             match line {
                 Ok(line) => {
 
+                    if line.starts_with("Island:") {
+                        let k = line[7..].trim().parse::<usize>().unwrap_or(usize::MAX);
+                        if k >= self.islands.len() {
+                            mem::swap(&mut self.forest, &mut self.islands[island].forest);
+                            return Err(std::io::Error::new(
+                                std::io::ErrorKind::InvalidData,
+                                format!("{} but there are {} islands",
+                                        line, self.islands.len())));
+                        }
+                        mem::swap(&mut self.forest, &mut self.islands[island].forest);
+                        island = k;
+                        mem::swap(&mut self.forest, &mut self.islands[island].forest);
+                        continue;
+                    }
+                    if line.starts_with("Front") {
+                        // The Pareto front repeats trees already read
+                        continue;
+                    }
+                    if line.starts_with("Policy:") {
+                        // Trees are evaluated as they were when they
+                        // were saved
+                        let policy = Policy::new_from_str(line[7..].trim());
+                        if policy != self.policy {
+                            eprintln!("Policy {} from save file not {}",
+                                      policy.to_string(), self.policy.to_string());
+                            self.policy = policy;
+                        }
+                        continue;
+                    }
+
                     // Split the line into ws separated words. Iterate
                     // over words and set `f` at "Node:".  Tree is
                     // after that
@@ -438,20 +1327,36 @@ impl Population {
                     }
                     // tree is string representation of a tree
                     let n = Box::new(Node::new_from_str(tree.as_str()));
-                    match  score_individual(&n, &self.data, true) {
+                    let n = Population::_rationalise(n, self.simplify, self.policy);
+                    if self.forest.has_tree_nb(&n) {
+                        // Simplified to a tree already recreated
+                        bnd.write_line(&format!("Recreate Duplicate {}",
+                                                n.to_string()));
+                        continue;
+                    }
+                    if !n.type_check(&self.primitives) {
+                        // Saved with other units, or a different
+                        // objective
+                        bnd.write_line(&format!("Recreate Ill-typed {}",
+                                                n.to_string()));
+                        continue;
+                    }
+                    match  score_individual(&n, &self.data, Partition::Training, self.policy,
+                                            self.fitness) {
                         Ok(sc) => {
                             if sc.is_finite() {
-                                let id = self.forest.maxid + 1;
+                                let id = self._maxid() + 1;
                                 {
                                     bnd.write_line(&format!("Recreate {}/(Sc: {}) {}",
                                                             id, &sc.quality(), n.to_string()));
                                 }
-                                self.forest.insert(Tree{id:id, score:sc, tree:n});
+                                self.forest.insert(Tree::new(id, sc, n, &self.data, self.policy,
+                                                                       self.fitness));
                                 self.forest.maxid = id;
                             }
                         },
                         Err(e) => {
-                            let s = format!("Recreate Failed {:?}  {}",
+                            let s = format!("Recreate Failed {}: {}",
                                             e, n.to_string());
                             bnd.write_line(&s);
                         },
@@ -460,6 +1365,7 @@ impl Population {
                 Err(e) => panic!("{}", e),
             };
         }
+        mem::swap(&mut self.forest, &mut self.islands[island].forest);
         Ok(())
     }
 
@@ -483,7 +1389,7 @@ impl Population {
 
         let mut ret = Forest::new();
         ret.maxid = forest.maxid;
-        for (_, v) in forest.trees.iter() {
+        for v in forest.by_id() {
             if v.score.is_finite() {
                 ret.insert(v.clone());
             }else{
@@ -511,52 +1417,144 @@ impl Population {
     //
     // Selection algorithms.
     //
-    fn roulette_selection(&self, wheel:&Vec<(usize, f64)>) -> usize {
+    // The selection for the current generation.  See selection.rs
+    fn _selector(&self) -> Box<dyn Selection> {
+        match self.selection {
+            Scheme::Roulette => {
+                let wheel = Population::_make_wheel(
+                    &Population::_parsimonious(&self.forest, &self.parsimony)[..]);
+                eprintln!("Wheel: {:?}", wheel);
+                Box::new(Roulette::new(wheel))
+            },
+            Scheme::Tournament(size) => {
+                let candidates = Population::_parsimonious(&self.forest, &self.parsimony);
+                Box::new(Tournament::new(candidates, size))
+            },
+            Scheme::Lexicase => {
+                let candidates = self.forest.trees.iter().map(|(_, t)| {
+                    (t.id, t.score.errors.clone())
+                }).collect();
+                Box::new(Lexicase::new(candidates))
+            },
+            Scheme::Pareto => {
+                let (trees, points) = Population::_objective_points(&self.forest,
+                                                                    &self.objectives);
+                let candidates = trees.iter().zip(pareto::rank(&points[..])).
+                    map(|(s, (r, c))| (self.forest.trees.get(s).unwrap().id, r, c)).
+                    collect();
+                Box::new(Crowded::new(candidates))
+            },
+        }
+    }
 
-        // https://en.wikipedia.org/wiki/Fitness_proportionate_selection
-        // Return the id of a individual selected using roulette wheel
-        // selection:
-        // FIXME Return a reference to a tree not a id
-        
-        let sel = rng::gen_range(0.0, 1.0); 
-        // `sel` is the selector for the "roulette wheel".  
-        let mut  acc = 0.0;
-
-        // To output the size of the wheel area the selected
-        // individual has so <size in wheel> V. <frequencey of
-        // selection> can be ploted
-        let mut debug_s = 0.0;
-        
-        let mut ret:usize = 0;  // Index of selected individual
-        for (i, s) in wheel.iter() {
-            acc += s;
-            if acc > sel {
-                // Have the tree's string rep in t.  Get the
-                // actual tree's id.
-                ret = *i;
-                debug_s = *s;
-                break;
+    // Score trees on a thread for each cache.  Thread `k` scores
+    // every `k`th tree.  The scores are in the order of the trees, so
+    // the number of threads does not change the result.  The scores
+    // are on the training, validation and test data.  Only scoring
+    // on the training data uses the cache
+    fn _score_all(trees:&[&NodeBox], data:&Data, policy:Policy, fitness:Fitness,
+                  caches:&mut [Cache]) -> Vec<Result<Tree, ScoreError>> {
+        let score = |t:&NodeBox, cache:&mut Cache| {
+            score_individual_cached(t, data, Partition::Training, policy, fitness, cache).
+                map(|sc| Tree::new(0, sc, t.copy(), data, policy, fitness))
+        };
+        if caches.len() == 1 || trees.len() < 2 {
+            return trees.iter().map(|t| score(t, &mut caches[0])).collect();
+        }
+        let n = caches.len();
+        let mut ret:Vec<Option<Result<Tree, ScoreError>>> = trees.iter().map(|_| None).collect();
+        thread::scope(|s| {
+            let handles:Vec<_> = caches.iter_mut().enumerate().map(|(k, cache)| {
+                s.spawn(move || {
+                    trees.iter().enumerate().skip(k).step_by(n).map(|(i, t)| {
+                        (i, score(t, cache))
+                    }).collect::<Vec<_>>()
+                })
+            }).collect();
+            for h in handles {
+                for (i, r) in h.join().expect("Scoring thread failed") {
+                    ret[i] = Some(r);
+                }
             }
+        });
+        ret.into_iter().map(|r| r.unwrap()).collect()
+    }
+
+    // The id and quality of each tree less the parsimony penalty for
+    // its size, in order of id
+    fn _parsimonious(forest:&Forest, params:&ParsimonyParams) -> Vec<(usize, f64)> {
+        let trees:Vec<(usize, f64, f64)> = forest.by_id().iter().map(|t| {
+            (t.id, t.score.quality(), params.size(&t.score))
+        }).collect();
+        let c = match params.pressure {
+            Parsimony::Off => 0.0,
+            Parsimony::Linear(c) => c,
+            Parsimony::Covariant => {
+                let n = trees.len() as f64;
+                let ms = trees.iter().map(|t| t.2).sum::<f64>() / n;
+                let mq = trees.iter().map(|t| t.1).sum::<f64>() / n;
+                let cov = trees.iter().map(|t| (t.2 - ms) * (t.1 - mq)).sum::<f64>() / n;
+                let var = trees.iter().map(|t| (t.2 - ms).powi(2)).sum::<f64>() / n;
+                if var > 0.0 {cov / var} else {0.0}
+            },
+        };
+        trees.iter().map(|t| (t.0, t.1 - c * t.2)).collect()
+    }
+
+    // The trees, in order of id, and their objectives
+    fn _objective_points(forest:&Forest,
+                         objectives:&[Objective]) -> (Vec<String>, Vec<Vec<f64>>) {
+        let trees = forest.by_id();
+        let points = trees.iter().map(|t| {
+            objectives.iter().map(|o| o.value(&t.score)).collect()
+        }).collect();
+        (trees.iter().map(|t| t.tree.to_string()).collect(), points)
+    }
+
+    // Keep the `n` trees NSGA-II prefers
+    fn _pareto_survivors(forest:&mut Forest, objectives:&[Objective], n:usize,
+                         bnd_rec:&mut Recorder) {
+        let (trees, points) = Population::_objective_points(forest, objectives);
+        let mut keep = vec![false; trees.len()];
+        for i in pareto::survivors(&points[..], n) {
+            keep[i] = true;
         }
-        {
-            let f = Population::_get_tree_id(&self.forest, ret);
-            eprintln!("Roulette select id: {} wheel: {} tree: {}",
-                      ret, debug_s, f.tree.to_string());
+        for (t, k) in trees.iter().zip(keep) {
+            if !k {
+                let id = forest.delete_str(t.as_str());
+                bnd_rec.write_line(&format!("RIP {} culled", id));
+            }
         }
-        ret
+        assert!(forest._check_sz() == 0);
     }
 
     // End of selection algorithms
     //
     // ========================================
 
+    /// Simplify a new tree if configured to.  Then duplicates that
+    /// only differ by redundant structure are found
+    fn _rationalise(n:NodeBox, simplify:bool, policy:Policy) -> NodeBox {
+        if simplify {
+            NodeBox::new(n.rationalise(policy))
+        }else{
+            n
+        }
+    }
+
     fn _add_individual(d_all:&Data,
+                       prims:&Primitives,
+                       simplify:bool,
+                       policy:Policy,
+                       fitness:Fitness,
                        bnd_rec:&mut Recorder,
-                       forest:&mut Forest) -> bool {
+                       forest:&mut Forest,
+                       rng:&mut Stream) -> bool {
         
         // Add a random individuall.  If the individual is already in
         // the population do not add it and return false
-        let n = Box::new(Node::new(&d_all.input_names, 0));
+        let n = Box::new(Node::new_program(prims, rng));
+        let n = Population::_rationalise(n, simplify, policy);
 
         let st = n.to_string();
         if !forest.trees.contains_key(&st.clone()) {
@@ -564,14 +1562,17 @@ impl Population {
             // This node is unique
             let id = forest.maxid + 1;
 
-            match  score_individual(&n, d_all, true) {
+            match  score_individual(&n, d_all, Partition::Training, policy, fitness) {
                 Ok(sc) => {
                     bnd_rec.write_line(&format!("Create {}/(Sc: {}) {}", id, sc.quality(), n.to_string())); 
-                    forest.insert(Tree{id:id, score:sc, tree:n});
+                    forest.insert(Tree::new(id, sc, n, d_all, policy, fitness));
                     forest.maxid = id;
                     true
                 },
-                Err(_) => false,
+                Err(e) => {
+                    bnd_rec.write_line(&format!("Create Failed {}", e));
+                    false
+                },
             }
         }else{
             false
@@ -579,7 +1580,8 @@ impl Population {
     }
     pub fn _initialise_rand(&mut self,
                             bnd_rec:&mut Recorder,
-                            max_population:usize){
+                            max_population:usize,
+                            rng:&mut Stream){
         // Initialise with a random tree
 
         loop {
@@ -588,7 +1590,9 @@ impl Population {
             // unique individual is created.  FIXME FIXTHAT!
             // _add_individual should be much more deterministic, pseudo
             // random
-            while !Population::_add_individual(&self.data, bnd_rec, &mut self.forest) {} 
+            while !Population::_add_individual(&self.data, &self.primitives,
+                                               self.simplify, self.policy, self.fitness,
+                                               bnd_rec, &mut self.forest, rng) {} 
 
             if self.forest.trees.len() == max_population {
                 break;
@@ -623,149 +1627,351 @@ impl Population {
         
     }
 
-    fn _do_crossover(&self, wheel:&Vec<(usize, f64)>)  -> (NodeBox, usize, usize){
+    fn _do_crossover(&self, selector:&dyn Selection,
+                     rng:&mut Stream)  -> (NodeBox, usize, usize){
         // FIXME There is no concept of "attraction" here.  There
         // could be some algorithm where the second tree selected
         // could depend on the first.  
         let i0;
-        i0 = self.roulette_selection(wheel);
-        let i1 = self.roulette_selection(wheel);
-        (self._crossover(i0, i1), i0, i1)
-    }
-
-    fn _mutate_tree(i:NodeBox, d_all:&Data) -> NodeBox {
-        // How many nodes are there?
-        let nc = i.count_nodes();
-        // In decision branch?
-        let dnc = match i.d {
-            Some(ref d) => {
-                d.count_nodes()
-            },
-            None => 0,
-        };
-        // In left child?
-        let lnc = match i.l {
-            Some(ref l) => l.count_nodes(),
-            None => 0,
+        i0 = selector.select(rng);
+        let i1 = selector.select(rng);
+        (self._crossover(i0, i1, rng), i0, i1)
+    }
+
+    // Mutate a tree.  The mutant and the kind of mutation done.
+    // `None` if the tree could not be changed
+    fn _mutate_tree(i:NodeBox, prims:&Primitives,
+                    params:&MutationParams, rng:&mut Stream) -> Option<(NodeBox, Mutation)> {
+        // Choose the kind of mutation.  If it cannot be done to this
+        // tree replace a subtree
+        let kind = params.choose(rng);
+        let m = match kind {
+            Mutation::Point => Population::_point_mutate(&i, prims, rng),
+            Mutation::Hoist => Population::_hoist(&i, prims, rng),
+            Mutation::Shrink => Population::_shrink(&i, prims, rng),
+            _ => None,
         };
-        // In right child
-        let rnc = match i.r {
-            Some(ref r) => r.count_nodes(),
-            None => 0,
+        if let Some(m) = m {
+            return Some((m, kind));
+        }
+
+        // Choose the node to mutate.  Any node in any branch
+        let points = i.points();
+        let (n, branch) = points[rng.gen_range(0, points.len())];
+
+        let mut ret = i;
+        let children:Vec<usize> = ret.get_node(n).children().iter().
+            map(|c| c.count_nodes()).collect();
+
+        // Two cases: This is a terminal, this is not terminal
+        if children.len() == 0 {
+            // A terminal.  Move a constant or change what it is
+            let t = ret.get_node(n).terminal().unwrap().clone();
+            let (node, kind) = Population::_mutate_terminal(&t, branch, prims, params, rng);
+            let mut m = ret.copy();
+            m.replace_node(n, NodeBox::new(node));
+            if m.type_check(prims) {
+                Some((m, kind))
+            }else if let TerminalType::Float(f) = t {
+                // An input in the wrong unit replaced a constant.
+                // Move the constant instead
+                let f = f + params.constant_step * rng.normal();
+                ret.replace_node(n, NodeBox::new(
+                    Node::new_terminal(TerminalType::Float(f))));
+                Some((ret, Mutation::Gaussian))
+            }else{
+                // An input or argument replaced with one that does
+                // not fit, as can happen in a reloaded tree.  No
+                // mutation
+                None
+            }
+        }else{
+            // Not terminal.  Replace a child with a new random
+            // subtree built for the same branch, in the same unit so
+            // the programme still makes sense.  A constant can have
+            // any unit, so what replaces it is checked
+            let selector = rng.gen_range(0, children.len());
+            let c = n + 1 + children[..selector].iter().sum::<usize>();
+            let unit = match ret.get_node(c).unit(&prims.units) {
+                Ok(u) => u,
+                // A reloaded tree that does not type check.  No
+                // mutation
+                Err(_) => return None,
+            };
+            for _ in 0..10 {
+                let mut m = ret.copy();
+                m.replace_node(c, NodeBox::new(Node::new(prims, branch, 0, &unit, rng)));
+                if m.type_check(prims) {
+                    return Some((m, Mutation::Subtree));
+                }
+            }
+            None
+        }
+    }
+
+    // Replace the operator of a node with another with as many
+    // arguments.  `None` if no node can be changed
+    fn _point_mutate(i:&NodeBox, prims:&Primitives, rng:&mut Stream) -> Option<NodeBox> {
+        let internal:Vec<usize> = i.points().iter().map(|p| p.0).
+            filter(|n| i.get_node(*n).terminal().is_none()).collect();
+        if internal.len() == 0 {
+            return None;
+        }
+        for _ in 0..10 {
+            let n = internal[rng.gen_range(0, internal.len())];
+            let mut m = i.copy();
+            if m.point_mutate(n, prims, rng) && m.type_check(prims) {
+                return Some(m);
+            }
+        }
+        None
+    }
+
+    // Replace the result producing branch with one of its subtrees.
+    // ADFs are kept
+    fn _hoist(i:&NodeBox, prims:&Primitives, rng:&mut Stream) -> Option<NodeBox> {
+        let points:Vec<usize> = i.points().iter().
+            filter(|p| p.1 == Branch::Result).map(|p| p.0).collect();
+        if points.len() < 2 {
+            return None;
+        }
+        let root = *points.iter().min().unwrap();
+        for _ in 0..10 {
+            let n = points[rng.gen_range(0, points.len())];
+            if n == root {
+                continue;
+            }
+            let mut m = i.copy();
+            m.replace_node(root, i.get_node(n).copy());
+            if m.type_check(prims) {
+                return Some(m);
+            }
+        }
+        None
+    }
+
+    // Replace a subtree with a terminal with the same unit
+    fn _shrink(i:&NodeBox, prims:&Primitives, rng:&mut Stream) -> Option<NodeBox> {
+        let internal:Vec<(usize, Branch)> = i.points().into_iter().
+            filter(|p| i.get_node(p.0).terminal().is_none()).collect();
+        if internal.len() == 0 {
+            return None;
+        }
+        let (n, branch) = internal[rng.gen_range(0, internal.len())];
+        let unit = match i.get_node(n).unit(&prims.units) {
+            Ok(u) => u,
+            Err(_) => return None,
         };
+        for _ in 0..10 {
+            let mut m = i.copy();
+            m.replace_node(n, NodeBox::new(Node::new_leaf(prims, branch, &unit, rng)));
+            if m.type_check(prims) {
+                return Some(m);
+            }
+        }
+        None
+    }
 
-        assert_eq!(dnc+lnc+rnc, nc-1);
+    // Tune the constants of the best trees.  A tuned copy that scores
+    // better is added to the population as a new individual
+    fn _tune(&mut self, bnd_rec:&mut Recorder) {
+        // `score_trees` is in order of score, best first
+        let best:Vec<Tree> = self.forest.score_trees.iter().
+            flat_map(|(_, v)| v.iter()).take(self.tune.count).
+            map(|s| self.forest.trees.get(s).unwrap().clone()).collect();
+        let d_all = &self.data;
+        let policy = self.policy;
+        let fitness = self.fitness;
+        for t in best {
+            let x0 = t.tree.constants();
+            if x0.len() == 0 {
+                continue;
+            }
 
-        // Choose which tree to mutate
-        let selector = rng::gen_range(0, nc+1);
-        if selector < dnc {
-            Population::_mutate_tree(i.d.unwrap(), d_all)
-        }else if selector < dnc + lnc {
-            Population::_mutate_tree(i.l.unwrap(), d_all)
-        }else if selector < dnc + lnc + rnc {
-            Population::_mutate_tree(i.r.unwrap(), d_all)
-        }else{
-            // Mutate i
-            // Two cases: This is a terminal, this is not terminal
-            if nc == 1 {
-                // i is a terminal.  FIXME  Mutate this!
-                i.copy()
-            }else{
-                // i is not terminal
-                let mut ret = i.copy();
-                let child = Node::new(&d_all.input_names, 0);
-                // Select which branch
-                let selector = rng::gen_range(0, nc-1);
-                if selector < lnc {
-                    ret.l = Some(NodeBox::new(child));
-                }else if selector < rnc + lnc {
-                    ret.r = Some(NodeBox::new(child));
-                }else if selector < dnc + lnc + rnc {
-                    ret.d = Some(NodeBox::new(child));
-                }else{
-                    panic!("selector {} is invalid lnc {} rnc {} dnc {} nc {}",
-                           selector, lnc, rnc, dnc, nc);
+            // Minimise minus the quality
+            let objective = |c:&[f64]| {
+                let mut n = t.tree.copy();
+                n.set_constants(c);
+                match score_individual(&n, d_all, Partition::Training, policy, fitness) {
+                    Ok(sc) => -sc.quality(),
+                    Err(_) => f64::INFINITY,
+                }
+            };
+            let (x, _) = self.tune.method.minimise(objective, &x0[..],
+                                                    self.tune.evaluations);
+            let mut n = t.tree.copy();
+            n.set_constants(&x[..]);
+            if self.forest.has_tree_nb(&n) {
+                continue;
+            }
+            if let Ok(sc) = score_individual(&n, d_all, Partition::Training, policy, fitness) {
+                if sc.quality() > t.score.quality() {
+                    let id = self.forest.maxid + 1;
+                    bnd_rec.write_line(&format!("Tune {} --> {}/(Sc: {}) {}",
+                                                t.id, id, sc.quality(), n.to_string()));
+                    self.forest.insert(Tree::new(id, sc, n, d_all, policy, fitness));
+                    self.forest.maxid = id;
                 }
-                ret
             }
         }
     }
+
+    // A mutated copy of a terminal in `branch`.  A constant is moved
+    // or becomes an input.  An input (or ADF argument) becomes another
+    // or a constant
+    fn _mutate_terminal(t:&TerminalType, branch:Branch, prims:&Primitives,
+                        params:&MutationParams, rng:&mut Stream) -> (Node, Mutation) {
+        // The arguments that can be used in this branch
+        let narg = match branch {
+            Branch::Result => 0,
+            Branch::Adf(k) => prims.adfs[k],
+        };
+        let x = rng.gen_range(0.0, 1.0);
+        match *t {
+            TerminalType::Float(f) => {
+                let n = prims.names.len() + narg;
+                if x < 0.2 && n > 0 {
+                    let b = rng.gen_range(0, n);
+                    let t = if b < prims.names.len() {
+                        TerminalType::Inputf64(prims.names[b].clone())
+                    }else{
+                        TerminalType::Arg(b - prims.names.len())
+                    };
+                    (Node::new_terminal(t), Mutation::ToInput)
+                }else if x < 0.6 {
+                    let f = f + params.constant_step * rng.normal();
+                    (Node::new_terminal(TerminalType::Float(f)), Mutation::Gaussian)
+                }else{
+                    let f = f * (params.constant_log_step * rng.normal()).exp();
+                    (Node::new_terminal(TerminalType::Float(f)), Mutation::LogScale)
+                }
+            },
+            TerminalType::Inputf64(ref s) => {
+                // The other inputs with the same unit
+                let unit = prims.units.get(s);
+                let others:Vec<&String> = prims.names.iter().filter(|n| {
+                    *n != s && prims.units.get(*n) == unit
+                }).collect();
+                if x < 0.7 && others.len() > 0 {
+                    let s = others[rng.gen_range(0, others.len())].clone();
+                    (Node::new_terminal(TerminalType::Inputf64(s)), Mutation::SwapInput)
+                }else{
+                    (Node::new_constant(rng), Mutation::ToConstant)
+                }
+            },
+            TerminalType::Arg(a) => {
+                if x < 0.7 && narg > 1 {
+                    // Any other argument
+                    let mut b = rng.gen_range(0, narg - 1);
+                    if b >= a {
+                        b += 1;
+                    }
+                    (Node::new_terminal(TerminalType::Arg(b)), Mutation::SwapInput)
+                }else{
+                    (Node::new_constant(rng), Mutation::ToConstant)
+                }
+            },
+        }
+    }
     // fn mutate_tree(&mut self,i:NodeBox) -> NodeBox {
     //     Population::_mutate_tree(i, &self.d_all)
     // }
     
-    fn _crossover(&self, lidx:usize, ridx:usize) -> NodeBox {
+    fn _crossover(&self, lidx:usize, ridx:usize, rng:&mut Stream) -> NodeBox {
         // FIXME Use references to nodes (and lifetimes?) insted of
         // indexes.  Save on lookups
 
         // Given the indexes of the a left and a right tree combine
-        // the two trees to make a third individual
-        let p:NodeBox;
-        let c:NodeBox;
-        if rng::random::<f64>() > 0.0 {
-            p = Population::_get_tree_id(&self.forest, lidx).tree.random_node();
-            c = Population::_get_tree_id(&self.forest, ridx).tree.random_node()
+        // the two trees to make a third individual.  A subtree of
+        // `p` is replaced with a subtree of `c` from the same branch
+        // so calls to ADFs and ADF arguments stay valid
+        let p:&NodeBox;
+        let c:&NodeBox;
+        if rng.random::<f64>() > 0.0 {
+            p = &Population::_get_tree_id(&self.forest, lidx).tree;
+            c = &Population::_get_tree_id(&self.forest, ridx).tree;
         }else{
-            c = Population::_get_tree_id(&self.forest, lidx).tree.random_node();
-            p = Population::_get_tree_id(&self.forest, ridx).tree.random_node()
+            c = &Population::_get_tree_id(&self.forest, lidx).tree;
+            p = &Population::_get_tree_id(&self.forest, ridx).tree;
         }
-        // Make the new tree by copying c
-        let c = c.copy();
 
-        // The tree to return
-        let mut ret = p.copy();
-        
-        // Choose a branch off p to copy c to
-        match p.d {
-            // This node has 3 legs
-
-            Some(_) =>
-                match rng::gen_range(0,3) {
-                    0 => (*ret).d = Some(c),
-                    1 => (*ret).l = Some(c),
-                    2 => (*ret).r = Some(c),
-                    _ => panic!("Impossible to get here"),
-                },
-            None =>
-                match p.r {
-                    Some(_) => 
-                    // This node has two legs
-                        if rng::random::<f64>()> 0.0 {
-                            (*ret).l = Some(c)
-                        }else{
-                            (*ret).r = Some(c)
-                        },
-                    None => (*ret).l = Some(c),
-                }
+        // Choose the node in p to replace
+        let points = p.points();
+        let (n, branch) = points[rng.gen_range(0, points.len())];
+
+        // The tree to return.  The subtree from c must leave the
+        // units making sense.  Try a few times then give up and
+        // return a copy of p
+        for _ in 0..10 {
+            let mut ret = p.copy();
+            match c.random_node(branch, rng) {
+                Some(s) => ret.replace_node(n, s),
+                None => break,
+            };
+            // Offspring over the size limits are rejected
+            if ret.type_check(&self.primitives) && self.parsimony.fits(&ret) {
+                return ret;
+            }
         }
-        ret
+        p.copy()
     }
 
-    fn _save_trees(forest:&Forest, save_file:&str){
+    fn _save_trees(islands:&[Island], policy:Policy, objectives:&[Objective],
+                   save_file:&str){
         
         let mut file = File::create(save_file).unwrap();
         file.lock_exclusive().expect("Failed to lock save file");
+
+        // Trees must be evaluated the same way when they are restored
+        file.write_all(format!("Policy: {}\n", policy.to_string()).as_bytes()).unwrap();
+        for (i, island) in islands.iter().enumerate() {
+            // The trees after this line are on island `i`
+            file.write_all(format!("Island: {}\n", i).as_bytes()).unwrap();
+            Population::_save_forest(&island.forest, objectives, &mut file);
+        }
+    }
+
+    fn _save_forest(forest:&Forest, objectives:&[Objective], file:&mut File) {
         for k in forest.score_trees.keys() {
             for t in forest.score_trees.get(&k).unwrap().iter() {
-                file.write_all(format!("Id: {} Score: {} Node: {}\n",
-                                       forest.trees.get(t).unwrap().id,
-                                       k.quality(), t).as_bytes()).unwrap();
+                let tree = forest.trees.get(t).unwrap();
+                file.write_all(format!("Id: {} Score: {} Validation: {} Test: {} Node: {}\n",
+                                       tree.id, k.quality(),
+                                       Population::_quality(&tree.validation),
+                                       Population::_quality(&tree.testing),
+                                       t).as_bytes()).unwrap();
+            }
+        }
+
+        // The first Pareto front, in order of the first objective.
+        // Restoring skips these lines
+        if objectives.len() > 0 && forest.trees.len() > 0 {
+            let (trees, points) = Population::_objective_points(forest, objectives);
+            let mut front = pareto::fronts(&points[..]).swap_remove(0);
+            front.sort_by(|a, b| points[*a][0].partial_cmp(&points[*b][0]).unwrap());
+            for i in front {
+                let mut line = format!("Front Id: {}", forest.trees.get(&trees[i]).unwrap().id);
+                for (o, v) in objectives.iter().zip(points[i].iter()) {
+                    line += format!(" {:?}: {}", o, v).as_str();
+                }
+                line += format!(" Node: {}\n", trees[i]).as_str();
+                file.write_all(line.as_bytes()).unwrap();
             }
         }
     }
     
-    fn _make_wheel(forest:&Forest, config:&Config) -> Vec<(usize, f64)> {
-        let mut max_score = 0.0;
+    // `qualities` is the id and (parsimonious) quality of each tree
+    fn _make_wheel(qualities:&[(usize, f64)]) -> Vec<(usize, f64)> {
+        let mut max_score = f64::MIN;
         let mut min_score  = f64::MAX;
-        let mut total = 0;
-        for (st, vt) in forest.score_trees.iter() {
-            let ts = st.quality();
-            if ts > max_score {
-                max_score = ts;
-            }else if ts < min_score {
-                min_score = ts;
+        let total = qualities.len();
+        for (_, ts) in qualities.iter() {
+            if *ts > max_score {
+                max_score = *ts;
+            }
+            if *ts < min_score {
+                min_score = *ts;
             }
-            total += vt.len();
         }
 
         // Build the abstract roulette wheel.  Each individual has
@@ -781,27 +1987,31 @@ impl Population {
         // all values assigned below that is used to normalise the
         // values
         let mut tot = 0.0;
-        let  ret:Vec<(usize, f64)> = forest.trees.iter().map(|(_, t)|{
-
-            let score = (t.id, (av + t.score.quality() - min_score)/(av + max_score - min_score));
-            let sz = (forest.count() as f64).log(10.0);
-            let v = ((config.get_f64("score_weight").unwrap()*score.1).powi(2) + 
-                     (config.get_f64("size_weight").unwrap()*sz).powi(2)).sqrt(); 
+        let  ret:Vec<(usize, f64)> = qualities.iter().map(|(id, q)|{
+            // If every score is the same every slot is
+            let v = if max_score > min_score {
+                (av + q - min_score)/(av + max_score - min_score)
+            }else{
+                1.0
+            };
             tot += v;
-            (t.id, v)
+            (*id, v)
         }).collect();
         ret.iter().map(|(id, x)| (*id, x/tot)).collect()
     }
-    fn _new_generation(&self,
+    fn _new_generation(&mut self,
                        mutate_prob:usize,
                        copy_prob:usize,
                        crossover_percent:usize, 
                        max_population:usize,
                        bnd_rec:&mut Recorder,
-                       save_file:&str) -> Forest // New trees
+                       rng:&mut Stream) -> Forest // New trees
     {
         let forest = &self.forest;
         let d_all = &self.data;
+        for cache in self.caches.iter_mut() {
+            cache.new_generation();
+        }
 
         let mut new_forest = Forest::new();
 
@@ -812,53 +2022,74 @@ impl Population {
 
 
 
-        let wheel = Population::_make_wheel(&self.forest, &self.config);
-        eprintln!("Wheel: {:?}", wheel);
+        let selector = self._selector();
         // Generate some of new population from the old population. The
         // number of crossovers to do is (naturally) population.len()
         // * crossover_percent/100
         let ncross = (forest.trees.len() * crossover_percent)/100;
         let mut nc = 0;
 
+        // The children are made first, using the random numbers in the
+        // same order however many threads there are, then scored
+        // together
+        let mut children:Vec<(NodeBox, usize, usize)> = Vec::new();
+        let mut pending:HashSet<String> = HashSet::new();
         while nc < ncross  {
 
-            let (nb, l, r) = self._do_crossover(&wheel);
-
-            let st = (*nb).to_string();
-            if !new_forest.has_tree_nb(&nb) {
+            let (nb, l, r) = self._do_crossover(&*selector, rng);
+            let nb = Population::_rationalise(nb, self.simplify, self.policy);
 
+            if pending.insert((*nb).to_string()) {
                 // A unique child in next generation
-                match score_individual(&nb, d_all, true) {
-                    Ok(sc) => {
-                        let id = new_forest.maxid+1;
-                        new_forest.insert(Tree{id:id, score:sc.clone(), tree:nb});
-                        new_forest.maxid = id;
-                        bnd_rec.write_line(&format!("Cross {} + {} --> {}/(Sc:{}): {}",
-                                                    l, r, id, &sc.quality(), st));
-                    },
-                    Err(e) => {
-                        bnd_rec.write_line(&format!("Cross Failed {:?} Cross {} + {} ",
-                                                    e, l, r));
-                        
-                    },
-                };
+                children.push((nb, l, r));
             }
             nc += 1;
         }
+        let scores = Population::_score_all(&children.iter().map(|c| &c.0).collect::<Vec<_>>()[..],
+                                            d_all, self.policy, self.fitness,
+                                            &mut self.caches[..]);
+        for ((nb, l, r), score) in children.into_iter().zip(scores) {
+            let st = (*nb).to_string();
+            match score {
+                Ok(t) => {
+                    let sc = t.score.clone();
+                    let id = new_forest.maxid+1;
+                    new_forest.insert(Tree{id:id, ..t});
+                    new_forest.maxid = id;
+                    bnd_rec.write_line(&format!("Cross {} + {} --> {}/(Sc:{}): {}",
+                                                l, r, id, &sc.quality(), st));
+                },
+                Err(e) => {
+                    bnd_rec.write_line(&format!("Cross Failed {} + {}: {}",
+                                                l, r, e));
+                    
+                },
+            };
+        }
         
-        // Do mutation.  Take mut_probab % of trees, mutate them, add
+        // Do mutation.  Select mut_probab % of trees, mutate them, add
         // them to the new population
-        for (_, t) in forest.trees.iter() {
-            if rng::gen_range(0, 100) < mutate_prob {
+        let mut children:Vec<(NodeBox, Mutation, usize)> = Vec::new();
+        for _ in 0..forest.trees.len() {
+            if rng.gen_range(0, 100) < mutate_prob {
 
                 // The id of the tree being mutated
-                let id0 = t.id;
+                let id0 = selector.select(rng);
 
                 // Copy the tree and mutate it.  Loose interest in
                 // original tree now
-                let t = t.tree.copy();
-
-                let nb = Population::_mutate_tree(t, d_all);
+                let t = Population::_get_tree_id(forest, id0).tree.copy();
+
+                let (nb, kind) = match Population::_mutate_tree(t, &self.primitives,
+                                                                &self.mutation, rng) {
+                    Some(m) => m,
+                    None => {
+                        bnd_rec.write_line(format!("Failed Mutate {}: No mutation fits",
+                                                   id0).as_str());
+                        continue;
+                    },
+                };
+                let nb = Population::_rationalise(nb, self.simplify, self.policy);
 
                 // Convert to a string to check for duplicates and for
                 // the record 
@@ -866,24 +2097,41 @@ impl Population {
                 if let Vacant(_) = new_forest.trees.entry(st.clone()) {
 
                     // Unique in the new population
-
-                    match  score_individual(&nb, d_all, true) {
-                        Ok(sc) => {
-                            new_forest.maxid += 1;
-                            let id = new_forest.maxid;
-                            new_forest.insert(Tree{id:id, score:sc.clone(), tree:nb});
-                            bnd_rec.write_line(format!("Mutate {} --> {}: {}/(Sc: {})",
-                                                       id0, new_forest.maxid, st, &sc.quality()).as_str());
-                        },
-                        Err(e) => bnd_rec.write_line(format!("Failed Mutate {:?}", e).as_str()),
-                    };
+                    if pending.insert(st) {
+                        children.push((nb, kind, id0));
+                    }
                 }                
             }
         }
+        let scores = Population::_score_all(&children.iter().map(|c| &c.0).collect::<Vec<_>>()[..],
+                                            d_all, self.policy, self.fitness,
+                                            &mut self.caches[..]);
+        for ((nb, kind, id0), score) in children.into_iter().zip(scores) {
+            let st = (*nb).to_string();
+            match score {
+                Ok(t) => {
+                    let sc = t.score.clone();
+                    new_forest.maxid += 1;
+                    let id = new_forest.maxid;
+                    new_forest.insert(Tree{id:id, ..t});
+                    bnd_rec.write_line(format!("Mutate {:?} {} --> {}: {}/(Sc: {})",
+                                               kind, id0, new_forest.maxid, st,
+                                               &sc.quality()).as_str());
+                },
+                Err(e) => bnd_rec.write_line(format!("Failed Mutate {:?} {}: {}",
+                                                     kind, id0, e).as_str()),
+            };
+        }
 
         // Copy the best trees.
         let mut cp = 0; // Number copied
-        let ncp = (forest.trees.len()*100)/copy_prob; // Number to copy
+        // Under Pareto selection every old tree competes with the new
+        // ones to survive
+        let ncp = if self.selection == Scheme::Pareto {
+            forest.trees.len()
+        }else{
+            (forest.trees.len()*100)/copy_prob // Number to copy
+        };
         for (_, vt) in forest.score_trees.iter() {
             //let it = forest.iter();
             // FIXME This could be probabilistic with roulette wheel
@@ -913,12 +2161,18 @@ impl Population {
         new_forest = Population::_cull_sort(&new_forest, bnd_rec);
 
         // Adjust population
+        if self.selection == Scheme::Pareto {
+            Population::_pareto_survivors(&mut new_forest, &self.objectives,
+                                          max_population, bnd_rec);
+        }
         while new_forest.trees.len() > max_population {
             Population::_delete_worst(&mut new_forest, bnd_rec);
         }
         let flag =  new_forest.trees.len() < max_population; // Set if new individuals  to be added
         while new_forest.trees.len() < max_population {
-            while Population::_add_individual(d_all, bnd_rec, &mut new_forest){}
+            while Population::_add_individual(d_all, &self.primitives,
+                                                 self.simplify, self.policy, self.fitness,
+                                                 bnd_rec, &mut new_forest, rng){}
         }
         if flag {
             // Sort again as we added new individuals. FIXME cull_sort
@@ -935,7 +2189,6 @@ impl Population {
             panic!("Check failed");
         }
 
-        Population::_save_trees(&new_forest, save_file);
         assert!(new_forest._check_sz() == 0);
 
         new_forest 
@@ -949,31 +2202,111 @@ impl Population {
 mod tests {
     
     use config::Config;
+    use inputs::Inputs;
+    use node::EvalError;
     use node::Node;
     use node::NodeBox;
     use population::Forest;
     use population::Tree;
     use score::Score;
+    use units::Unit;
     use std::collections::BTreeMap;    
     use std::collections::HashMap;    
     use std::fs;
     use std::fs::File;
     use std::path::Path;
+    use std::sync::Arc;
     use super::*;
     
+    /// The best trees of each island migrate and the islands keep
+    /// their size
+    #[test]
+    fn test_migrate() {
+        let mut rng = Stream::new(&[1], "test");
+        let params = GenerationParams{mutate_prob:0, copy_prob:1, crossover_percent:0,
+                                      max_population:2};
+        let mut islands:Vec<Island> = (0..3).map(|i| {
+            let mut forest = Forest::new();
+            for j in 0..2 {
+                let q = 0.1 * (j + 1) as f64 + 0.3 * i as f64;
+                forest.insert(Tree{
+                    id:10 * i + j,
+                    score:Score{quality:q, class:None, errors:Arc::new(vec![]), metric:0.0, error:0.0,
+                                size:1, depth:1, inputs:0},
+                    validation:None,
+                    testing:None,
+                    tree:NodeBox::new(Node::new_from_str(&format!("Float {}", q))),
+                });
+            }
+            Island{forest:forest, params:params}
+        }).collect();
+        assert_eq!(Topology::Ring.destinations(2, 3, &mut rng), vec![0]);
+        assert_eq!(Topology::Full.destinations(1, 3, &mut rng), vec![0, 2]);
+        assert_ne!(Topology::Random.destinations(1, 3, &mut rng), vec![1]);
+
+        let migration = MigrationParams{interval:1, count:1, topology:Topology::Ring};
+        let mut bnd_rec = Recorder::new("/tmp/test_migrate_bnd");
+        Population::_migrate(&mut islands, &migration, &mut bnd_rec, &mut rng);
+        for island in islands.iter() {
+            assert_eq!(island.forest.trees.len(), 2);
+        }
+        // The best of island 2 (id 21) replaces the worst of island 0
+        let ids:Vec<usize> = islands[0].forest.trees.values().map(|t| t.id).collect();
+        assert!(ids.contains(&21) && ids.contains(&1) && !ids.contains(&0));
+    }
+
+    /// Scoring on several threads gives the scores one thread does,
+    /// in the same order
+    #[test]
+    fn test_score_all() {
+        let mut d = Data{
+            names:vec!["x".to_string(), "y".to_string()],
+            units:vec![None, None],
+            input_names:vec!["x".to_string()],
+            data:(0..20).map(|i| vec![i as f64, 2.0 * i as f64]).collect(),
+            columns:vec![],
+            testing_i:vec![],
+            training_i:(0..20).collect(),
+            validation_i:vec![],
+            classes:vec![],
+        };
+        d.columnate();
+        let trees:Vec<NodeBox> = vec!["Add x x", "Multiply x Float 3", "Log Negate x",
+                                      "x", "Add x Float 1"].iter().
+            map(|s| NodeBox::new(Node::new_from_str(s))).collect();
+        let trees:Vec<&NodeBox> = trees.iter().collect();
+        let one = Population::_score_all(&trees[..], &d, Policy::Strict, Fitness::Rmse,
+                                         &mut [Cache::new(0)]);
+        let mut caches:Vec<Cache> = (0..3).map(|_| Cache::new(1024 * 1024)).collect();
+        let three = Population::_score_all(&trees[..], &d, Policy::Strict, Fitness::Rmse,
+                                           &mut caches[..]);
+        assert_eq!(one.len(), 5);
+        assert!(one[2].is_err() && three[2].is_err());
+        for (a, b) in one.iter().zip(three.iter()) {
+            if let (Ok(a), Ok(b)) = (a, b) {
+                assert!(a.score == b.score);
+            }
+        }
+        assert_eq!(one[0].as_ref().unwrap().score.quality, 1.0);
+    }
+
     /// Test constructing a roulette wheel
     #[test]
     fn test_wheel() {
         let mut trees:HashMap<String, Tree> = HashMap::new();
         let s = "Float 0.1";
+        let score = Score{quality:1.0, class:None, errors:Arc::new(vec![]), metric:0.0, error:0.0,
+                          size:1, depth:1, inputs:0};
         let t = Tree{
             id:0,
-            score:Score{quality:1.0},
+            score:score.clone(),
+            validation:None,
+            testing:None,
             tree:NodeBox::new(Node::new_from_str(s)),
         };
         trees.insert(s.to_string(), t);
         let mut score_trees:BTreeMap<Score, Vec<String>> = BTreeMap::new();
-        score_trees.insert(Score{quality:1.0}, vec![s.to_string()]);
+        score_trees.insert(score.clone(), vec![s.to_string()]);
         let maxid = trees.len();
         let forest = Forest {
             trees:trees,
@@ -981,17 +2314,219 @@ mod tests {
             maxid:maxid,
         };
 
-        let mut data:HashMap<String, String> = HashMap::new();
-        data.insert("score_weight".to_string(), "1".to_string());
-        data.insert("size_weight".to_string(), "1".to_string());
-        let config = Config {
-            data:data,
-        };
-        
-        let wheel = Population::_make_wheel(&forest, &config);
+        let mut params = ParsimonyParams{pressure:Parsimony::Off, depth:false,
+                                         max_size:None, max_depth:Some(3)};
+        let wheel = Population::_make_wheel(&Population::_parsimonious(&forest, &params)[..]);
         assert_eq!(wheel.len(), 1);
         assert_eq!(wheel[0].0, 0);
         // assert_eq!(wheel.len(), 1);
+
+        // As good but bigger.  Parsimony makes its slot smaller
+        let mut forest = forest;
+        let s = "Add Float 0.1 Multiply x Float 0";
+        forest.insert(Tree{
+            id:1,
+            score:Score{size:5, depth:3, ..score.clone()},
+            validation:None,
+            testing:None,
+            tree:NodeBox::new(Node::new_from_str(s)),
+        });
+        let wheel = Population::_make_wheel(&Population::_parsimonious(&forest, &params)[..]);
+        assert_eq!(wheel.iter().find(|w| w.0 == 0).unwrap().1, 0.5);
+        params.pressure = Parsimony::Linear(0.01);
+        let wheel = Population::_make_wheel(&Population::_parsimonious(&forest, &params)[..]);
+        assert!(wheel.iter().find(|w| w.0 == 0).unwrap().1 > 0.5);
+
+        // Hard limits
+        let big = &Population::_get_tree_id(&forest, 1).tree;
+        assert!(params.fits(big));
+        params.max_depth = Some(2);
+        assert!(!params.fits(big));
+    }
+    #[test]
+    /// Mutating a programme with ADFs leaves a valid programme
+    fn test_mutate_adf() {
+        let mut rng = Stream::new(&[1], "test");
+        let prims = Primitives{
+            names:vec!["x".to_string(), "y".to_string()],
+            adfs:vec![2, 1],
+            units:HashMap::new(),
+            objective:None,
+            functions:FunctionSet::new(),
+        };
+        let mut inputs = Inputs::new();
+        inputs.insert("x", 1.0);
+        inputs.insert("y", 2.0);
+        let params = MutationParams{
+            kinds:MutationParams::kinds_from_str("Subtree Point Hoist Shrink"),
+            constant_step:1.0, constant_log_step:0.5};
+        let mut n = NodeBox::new(Node::new_program(&prims, &mut rng));
+        for _ in 0..100 {
+            n = match Population::_mutate_tree(n.copy(), &prims, &params, &mut rng) {
+                Some((m, _)) => m,
+                None => n,
+            };
+            let s = n.to_string();
+            assert_eq!(Node::new_from_str(s.as_str()).to_string(), s);
+            // Domain errors are possible, but not a missing child
+            // or argument
+            match n.evaluate(&inputs, Policy::Strict) {
+                Err(EvalError::MissingChild(e)) | Err(EvalError::MissingInput(e)) =>
+                    panic!("{}: {}", e, s),
+                _ => (),
+            }
+        }
+    }
+    #[test]
+    /// Terminals are mutated to terminals that keep the units right
+    fn test_mutate_terminal() {
+        let mut rng = Stream::new(&[1], "test");
+        let mut units = HashMap::new();
+        units.insert("x".to_string(), Unit::new_from_str("m"));
+        units.insert("y".to_string(), Unit::new_from_str("m"));
+        units.insert("z".to_string(), Unit::new_from_str("s"));
+        let prims = Primitives{
+            names:vec!["x".to_string(), "y".to_string(), "z".to_string()],
+            adfs:vec![],
+            units:units,
+            objective:Some(Unit::new_from_str("m")),
+            functions:FunctionSet::new(),
+        };
+        let params = MutationParams{kinds:vec![(Mutation::Subtree, 1.0)],
+                                    constant_step:1.0, constant_log_step:0.5};
+        let s = "Add x Float 2.0";
+        let mut kinds = Vec::new();
+        for _ in 0..200 {
+            let n = NodeBox::new(Node::new_from_str(s));
+            let (m, kind) = match Population::_mutate_tree(n, &prims, &params, &mut rng) {
+                Some(m) => m,
+                None => continue,
+            };
+            assert!(m.type_check(&prims), "{:?} {}", kind, m.to_string());
+            match kind {
+                Mutation::Subtree => (),
+                _ => {
+                    // Only `x` can be swapped for `y`
+                    assert_eq!(m.count_nodes(), 3);
+                    assert!(!m.to_string().contains("z"), "{:?} {}", kind, m.to_string());
+                },
+            }
+            kinds.push(kind);
+        }
+        for k in vec![Mutation::Subtree, Mutation::Gaussian, Mutation::LogScale,
+                      Mutation::SwapInput, Mutation::ToConstant] {
+            assert!(kinds.contains(&k), "{:?}", k);
+        }
+
+        // A tree that does not type check, as a reloaded one might
+        // not, is not mutated rather than failing the run.  Unless a
+        // constant is moved or the mutant type checks
+        for _ in 0..50 {
+            let n = NodeBox::new(Node::new_from_str("Add Add z Float 1.0 y"));
+            if let Some((m, kind)) = Population::_mutate_tree(n, &prims, &params, &mut rng) {
+                assert!(kind == Mutation::Gaussian || m.type_check(&prims),
+                        "{:?} {}", kind, m.to_string());
+            }
+        }
+    }
+    #[test]
+    /// Point mutation keeps the shape of the tree, hoist and shrink
+    /// make it smaller
+    fn test_mutate_kinds() {
+        let mut rng = Stream::new(&[1], "test");
+        let prims = Primitives{
+            names:vec!["x".to_string(), "y".to_string()],
+            adfs:vec![],
+            units:HashMap::new(),
+            objective:None,
+            functions:FunctionSet::new(),
+        };
+        let s = "Add x Multiply y Negate Float 2.0";
+        for k in vec![Mutation::Point, Mutation::Hoist, Mutation::Shrink] {
+            let params = MutationParams{kinds:vec![(k, 1.0)],
+                                        constant_step:1.0, constant_log_step:0.5};
+            for _ in 0..20 {
+                let n = NodeBox::new(Node::new_from_str(s));
+                let (m, kind) = Population::_mutate_tree(n, &prims, &params, &mut rng).unwrap();
+                assert_eq!(kind, k);
+                let t = m.to_string();
+                match k {
+                    Mutation::Point => {
+                        assert_eq!(m.count_nodes(), 6);
+                        assert!(t.trim() != s, "{}", t);
+                    },
+                    _ => assert!(m.count_nodes() < 6, "{:?} {}", k, t),
+                }
+            }
+        }
+    }
+    #[test]
+    fn test_selection() {
+        let mut rng = Stream::new(&[1], "test");
+        let candidates = vec![(3, 0.5), (1, 0.1), (2, 0.9)];
+        // A big tournament is won by the best
+        let t = Tournament::new(candidates.clone(), 50);
+        assert_eq!(t.select(&mut rng), 2);
+        // A tournament of one is a random choice
+        let t = Tournament::new(candidates, 1);
+        let mut seen = Vec::new();
+        for _ in 0..100 {
+            seen.push(t.select(&mut rng));
+        }
+        for i in 1..4 {
+            assert!(seen.contains(&i));
+        }
+        let r = Roulette::new(vec![(4, 0.0), (5, 1.0)]);
+        assert_eq!(r.select(&mut rng), 5);
+
+        // Each of 1 and 2 is best on one case.  3 is never best
+        let l = Lexicase::new(vec![(1, Arc::new(vec![0.0, 5.0])),
+                                   (2, Arc::new(vec![5.0, 0.0])),
+                                   (3, Arc::new(vec![4.0, 4.0]))]);
+        let mut seen = Vec::new();
+        for _ in 0..100 {
+            seen.push(l.select(&mut rng));
+        }
+        assert!(seen.contains(&1));
+        assert!(seen.contains(&2));
+        assert!(!seen.contains(&3));
+    }
+    #[test]
+    /// The same seed selects the same trees however the forest was
+    /// built
+    fn test_selector_order() {
+        let home = "/tmp/Data/TestSelector";
+        fs::create_dir_all(home).unwrap();
+        let mut f = File::create(format!("{}/test_data", home)).unwrap();
+        f.write_all("x,y\n".as_bytes()).unwrap();
+        for i in 0..10 {
+            f.write_all(format!("{},{}\n", i, 2 * i).as_bytes()).unwrap();
+        }
+        let mut data:HashMap<String, String> = HashMap::new();
+        for &(k, v) in [("name", "TestSelector"), ("root_dir", "/tmp"),
+                        ("data_file", "test_data"), ("training_percent", "100"),
+                        ("seed", "7"), ("parsimony", "covariant")].iter() {
+            data.insert(k.to_string(), v.to_string());
+        }
+        let mut p = Population::new(&Config{data:data});
+        let trees:Vec<Tree> = (1..30).map(|i| {
+            let n = NodeBox::new(Node::new_from_str(
+                format!("Multiply x Float {}", 1.0 + i as f64 / 10.0).as_str()));
+            let sc = score_individual(&n, &p.data, Partition::Training, Policy::Strict,
+                                      Fitness::Rmse).unwrap();
+            Tree::new(i, sc, n, &p.data, Policy::Strict, Fitness::Rmse)
+        }).collect();
+        let mut selections = Vec::new();
+        for order in vec![trees.clone(), trees.into_iter().rev().collect()] {
+            p.forest = Forest::new();
+            for t in order {
+                p.forest.insert(t);
+            }
+            let selector = p._selector();
+            let mut rng = Stream::new(&[1], "test");
+            selections.push((0..50).map(|_| selector.select(&mut rng)).collect::<Vec<usize>>());
+        }
+        assert_eq!(selections[0], selections[1]);
     }
     #[test]
     /// Test simulating a tree
@@ -1039,4 +2574,112 @@ mod tests {
         assert!(r.is_ok());
         Ok(())
     }
+    #[test]
+    /// Test cross validating a tree.  It is exact on every fold
+    fn test_cross_validate() {
+        let home = "/tmp/Data/TestCV";
+        fs::create_dir_all(home).unwrap();
+        let mut f = File::create(format!("{}/test_data", home)).unwrap();
+        f.write_all("x,y\n".as_bytes()).unwrap();
+        for i in 0..11 {
+            f.write_all(format!("{},{}\n", i, 2 * i).as_bytes()).unwrap();
+        }
+        let mut data:HashMap<String, String> = HashMap::new();
+        for &(k, v) in [("name", "TestCV"), ("root_dir", "/tmp"),
+                        ("data_file", "test_data"), ("training_percent", "50"),
+                        ("seed", "7"), ("folds", "3"),
+                        ("simulate_tree", "Multiply x Float 2")].iter() {
+            data.insert(k.to_string(), v.to_string());
+        }
+        let mut p = Population::new(&Config{data:data.clone()});
+        assert!(p.cross_validate().unwrap());
+        let lines:Vec<String> = BufReader::new(
+            File::open(format!("{}/CrossValidation.txt", home)).unwrap()).
+            lines().map(|l| l.unwrap()).collect();
+        assert_eq!(lines, vec!["fold, size, rmse", "0, 4, 0", "1, 4, 0",
+                               "2, 3, 0", "mean, 3, 0", "sd, 3, 0"]);
+
+        // Evolving a model for each fold leaves the files of a run on
+        // all the data alone
+        let saved = "Id: 1 Score: 0 Node: Multiply x Float 2\n";
+        File::create(format!("{}/saved", home)).unwrap().write_all(saved.as_bytes()).unwrap();
+        let _ = fs::remove_file(format!("{}/saved.partition", home));
+        for &(k, v) in [("save_file", "saved"), ("birthsanddeaths_filename", "bnd.log"),
+                        ("generations_file", "gen.log"), ("reload", "false"),
+                        ("num_generations", "2"), ("max_population", "20"),
+                        ("mutate_prob", "10"), ("copy_prob", "10"),
+                        ("crossover_percent", "50")].iter() {
+            data.insert(k.to_string(), v.to_string());
+        }
+        data.remove("simulate_tree");
+        let mut p = Population::new(&Config{data:data});
+        assert!(p.cross_validate().unwrap());
+        assert_eq!(fs::read_to_string(format!("{}/saved", home)).unwrap(), saved);
+        assert!(!Path::new(&format!("{}/saved.partition", home)).exists());
+        for i in 0..3 {
+            for f in vec![format!("saved_fold{}", i), format!("saved_fold{}.partition", i),
+                          format!("bnd_fold{}.log", i), format!("gen_fold{}.log", i)] {
+                assert!(Path::new(&format!("{}/{}", home, f)).exists(), "{}", f);
+            }
+        }
+
+        // Failed folds are left out of the summary
+        let (mean, sd, n) = Population::_mean_sd(&[1.0, f64::NAN, 3.0]);
+        assert_eq!((mean, sd, n), (2.0, 2.0f64.sqrt(), 2));
+    }
+    #[test]
+    /// Test classifying with a tree for each of two classes
+    fn test_classify() {
+        let home = "/tmp/Data/TestClassify";
+        fs::create_dir_all(home).unwrap();
+        let mut f = File::create(format!("{}/test_data", home)).unwrap();
+        f.write_all("x,y\n".as_bytes()).unwrap();
+        for i in 0..20 {
+            f.write_all(format!("{},{}\n", i, if i < 10 {0} else {1}).as_bytes()).unwrap();
+        }
+        let mut f = File::create(format!("{}/saved", home)).unwrap();
+        f.write_all("Id: 1 Score: 0 Node: Subtract x Float 9.5\n\
+                     Id: 2 Score: 0 Node: Subtract Float 9.5 x\n".as_bytes()).unwrap();
+        let _ = fs::remove_file(format!("{}/saved.partition", home));
+        let mut data:HashMap<String, String> = HashMap::new();
+        for &(k, v) in [("name", "TestClassify"), ("root_dir", "/tmp"),
+                        ("data_file", "test_data"), ("training_percent", "50"),
+                        ("seed", "7"), ("classification", "true"),
+                        ("save_file", "saved"), ("birthsanddeaths_filename", "bnd")].iter() {
+            data.insert(k.to_string(), v.to_string());
+        }
+        let mut p = Population::new(&Config{data:data});
+        assert_eq!(p.fitness, Fitness::Hinge);
+        assert!(p.classify_test().unwrap());
+
+        // Each tree is for the class it is positive for
+        let mut classes:Vec<(usize, String)> = p.islands[0].forest.trees.values().
+            map(|t| (t.id, t.score.class.clone().unwrap())).collect();
+        classes.sort();
+        assert_eq!(classes, vec![(1, "1".to_string()), (2, "0".to_string())]);
+
+        let lines:Vec<String> = BufReader::new(
+            File::open(format!("{}/TestClassify_Classes.txt", home)).unwrap()).
+            lines().map(|l| l.unwrap()).collect();
+        assert_eq!(lines.len(), 10);
+        for l in lines {
+            // E.g: `0 Class: "1"  Classification: "1" 1.2 "0" -1.2`
+            let w:Vec<&str> = l.split_whitespace().collect();
+            assert_eq!((w[1], w[3], w.len()), ("Class:", "Classification:", 8));
+            assert_eq!(w[2], w[4]);
+        }
+        let (c, s) = p.classify(&[3.0, 0.0]).unwrap();
+        assert_eq!(c, "0");
+        assert!(s.starts_with("\"0\" "));
+
+        // The outputs are weighted by quality
+        let names = p.data.input_names.clone();
+        let classifiers = vec![
+            ("1".to_string(), 0.75, Node::new_from_str("Float 1.0").compile(&names[..])),
+            ("1".to_string(), 0.25, Node::new_from_str("Float -1.0").compile(&names[..])),
+        ];
+        let (c, s) = p._classify(&classifiers[..], &[3.0, 0.0]).unwrap();
+        assert_eq!(c, "1");
+        assert_eq!(s, "\"1\" 0.5 \"0\" 0");
+    }
 }
@@ -1,29 +1,72 @@
+//! Random numbers.  Each use of random numbers has its own stream,
+//! seeded from the `seed` configuration key and the name of the use,
+//! so a change in how one is used does not change the others.  See
+//! `Streams`
+
 use rand::{ChaChaRng, Rand, Rng, SeedableRng};
-use std::sync::{self, Mutex};
 use rand::distributions::range::SampleRange;
-lazy_static! {
-    static ref RNG: Mutex<ChaChaRng> = {
-        let rng = ::rand::random();
-        Mutex::new(rng)
-    };
+use rand::distributions::normal::StandardNormal;
+
+/// A named stream of random numbers
+pub struct Stream {
+    rng:ChaChaRng,
 }
 
+impl Stream {
+    /// The stream called `name` for `seed`.  The name is hashed into
+    /// one more word of seed
+    pub fn new(seed:&[u32], name:&str) -> Stream {
+        // FNV-1a
+        let mut h:u32 = 2166136261;
+        for b in name.bytes() {
+            h = (h ^ b as u32).wrapping_mul(16777619);
+        }
+        let mut words = seed.to_vec();
+        words.push(h);
+        Stream{rng:ChaChaRng::from_seed(&words[..])}
+    }
+
+    pub fn random<T: Rand>(&mut self) -> T {
+        self.rng.gen()
+    }
+
+    // A sample from the standard normal distribution
+    pub fn normal(&mut self) -> f64 {
+        let StandardNormal(x) = self.rng.gen::<StandardNormal>();
+        x
+    }
 
-// Convenience method
-pub fn reseed(seed: &[u32]) {
-    get().reseed(seed)
+    // Put a slice in a random order
+    pub fn shuffle<T>(&mut self, v:&mut [T]) {
+        self.rng.shuffle(v)
+    }
+
+    pub fn gen_range<T: PartialOrd+SampleRange>(&mut self, low: T, high: T) -> T{
+        self.rng.gen_range(low, high)
+    }
 }
 
-// Convenience method
-pub fn random<T: Rand>() -> T {
-    get().gen()
+/// The streams of a run
+pub struct Streams {
+    /// Making the initial population
+    pub initialise:Stream,
+
+    /// Selection, crossover, mutation and migration
+    pub variation:Stream,
 }
 
-pub fn gen_range<T: PartialOrd+SampleRange>(low: T, high: T) -> T{
-    get().gen_range(low, high)
+impl Streams {
+    pub fn new(seed:&[u32]) -> Streams {
+        Streams{
+            initialise:Stream::new(seed, "initialise"),
+            variation:Stream::new(seed, "variation"),
+        }
+    }
 }
-pub fn get<'a>() -> sync::MutexGuard<'a, ChaChaRng>{
-    RNG.lock().expect("Attempted to borrow the Rng multiple times!")
+
+/// A seed for when none is configured
+pub fn entropy_seed() -> Vec<u32> {
+    (0..4).map(|_| ::rand::random::<u32>()).collect()
 }
 
 
@@ -1,7 +1,13 @@
 use super::NodeBox;
 use super::Data;
-use inputs::Inputs;
+use data::Partition;
+use cache::Cache;
+use fitness::Fitness;
+use node::EvalError;
+use node::Policy;
 use std::cmp::Ordering;
+use std::fmt;
+use std::sync::Arc;
 
 /// Scoring a individual is key to evolving a good population of
 /// individuals.
@@ -24,6 +30,26 @@ pub struct Score {
     // Fitness calculated when classifying to self.class.unwrap()
     pub quality:f64,
 
+    // The class the individual is specialised for.  `None` unless
+    // classifying
+    pub class:Option<String>,
+
+    // The error for each case scored, in order, as the fitness
+    // function has it.  Shared as scores are copied to order trees
+    pub errors:Arc<Vec<f64>>,
+
+    // The value of the fitness function (see fitness.rs) that
+    // `quality` is made from
+    pub metric:f64,
+
+    // The objectives for Pareto selection (see pareto.rs).  The
+    // metric, negated if higher is better so it is minimised, the
+    // number of nodes, the depth and the number of distinct inputs of
+    // the tree
+    pub error:f64,
+    pub size:usize,
+    pub depth:usize,
+    pub inputs:usize,
 }
 
 impl Score {
@@ -42,7 +68,7 @@ impl Score {
 
     #[allow(dead_code)]
     pub fn copy(&self) -> Score {
-        Score{quality:self.quality, }
+        self.clone()
     }
 }
 
@@ -71,70 +97,138 @@ impl PartialOrd for Score {
 
 #[derive(Debug)]
 pub enum ScoreError {
-    FailedEvaluation,
+    // Evaluating the tree for one of the cases failed
+    FailedEvaluation(EvalError),
     NonFiniteSummation,
+
+    // Under the interval policy, the tree could have a value that is
+    // not finite
+    FailedIntervalCheck,
+}
+
+impl fmt::Display for ScoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ScoreError::FailedEvaluation(ref e) => write!(f, "{}", e),
+            ScoreError::NonFiniteSummation => write!(f, "Score not finite"),
+            ScoreError::FailedIntervalCheck => write!(f, "Failed interval check"),
+        }
+    }
 }
 pub fn score_individual(
     node:&NodeBox,
     d:&Data,
-    use_testing:bool) -> Result<Score, ScoreError> {
-
-    // Score individual is called once per node
-
-    // Get the data to do the evaluation on
-    let index:&Vec<usize>;
-    if use_testing {
-        index = &d.testing_i;
-    }else{
-        index = &d.training_i;
-    }
-
-    let mut inputs = Inputs::new();
+    partition:Partition,
+    policy:Policy,
+    fitness:Fitness) -> Result<Score, ScoreError> {
+    score_individual_cached(node, d, partition, policy, fitness, &mut Cache::new(0))
+}
 
+/// Score an individual using, and adding to, the values of subtrees
+/// in `cache`.  When classifying it is given the class it scores
+/// best for
+pub fn score_individual_cached(
+    node:&NodeBox,
+    d:&Data,
+    partition:Partition,
+    policy:Policy,
+    fitness:Fitness,
+    cache:&mut Cache) -> Result<Score, ScoreError> {
+    _score(node, d, partition, policy, fitness, None, cache)
+}
 
-    // Store each distance from the estimate to the actual value
-    // to calculate best and mean estimate
+/// Score an individual for `class`, the class it was given on the
+/// training data.  For the validation and test data
+pub fn score_for_class(
+    node:&NodeBox,
+    d:&Data,
+    partition:Partition,
+    policy:Policy,
+    fitness:Fitness,
+    class:&Option<String>) -> Result<Score, ScoreError> {
+    _score(node, d, partition, policy, fitness,
+           class.as_ref().map(|c| c.as_str()), &mut Cache::new(0))
+}
 
-    let mut y_d:Vec<f64> = Vec::new(); // Distances
+// Score against the true values or, when classifying, against each
+// class (or only `only`) one against the rest.  The best score is
+// returned
+fn _score(
+    node:&NodeBox,
+    d:&Data,
+    partition:Partition,
+    policy:Policy,
+    fitness:Fitness,
+    only:Option<&str>,
+    cache:&mut Cache) -> Result<Score, ScoreError> {
 
-    for i in index {
+    // Score individual is called once per node
 
-        // Examine each example
+    // Get the data to do the evaluation on
+    let index = d.rows(partition);
 
-        // The data for this example
-        let ref r = d.ith_row(*i);
+    if policy == Policy::Interval && node.interval(&d.ranges()).is_none() {
+        return Err(ScoreError::FailedIntervalCheck);
+    }
 
-        // Prepare the inputs to the simulation
-        for j in 0..d.input_names.len() {
-            let v:f64 = r[j];
-            let h = d.input_names[j].clone();
-            inputs.insert(h.as_str(), v);
+    // Get the estimates for every example.  With a cache a column at
+    // a time, so the values of subtrees found for other trees are
+    // used.  Without one the compiled tree is run on each row
+    let estimate = if cache.enabled() {
+        cache.prepare(&index[..], policy);
+        node.evaluate_columns_cached(&d.input_names, &d.columns,
+                                     &index[..], policy, cache)
+    }else{
+        let code = node.compile(&d.input_names);
+        index.iter().map(|i| code.evaluate(&d.data[*i], policy)).collect()
+    };
+    let estimate:Vec<f64> = match estimate {
+        Ok(e) => e,
+        Err(e) => return Err(ScoreError::FailedEvaluation(e)),
+    };
+
+    // The true values
+    let ref t = d.columns[d.names.len()-1];
+    let truth:Vec<f64> = index.iter().map(|i| t[*i]).collect();
+
+    // What to score against.  For a class the true value is 1 if the
+    // case is of the class and -1 if not
+    let targets:Vec<(Option<String>, Vec<f64>)> = if d.classes.is_empty() {
+        vec![(None, truth)]
+    }else{
+        d.classes.iter().map(|c| (Data::class_name(*c), *c)).
+            filter(|&(ref name, _)| only.map_or(true, |o| o == name.as_str())).
+            map(|(name, c)| {
+                (Some(name), truth.iter().map(|t| if *t == c {1.0} else {-1.0}).collect())
+            }).collect()
+    };
+
+    let f = fitness.function();
+    let mut best:Option<Score> = None;
+    for (class, truth) in targets {
+        let metric = f.metric(&truth[..], &estimate[..]);
+
+        // Must be increasing.  Maximum is 1, minimum aproaches 0
+        let s = f.quality(metric);
+        if !s.is_finite() || best.as_ref().map_or(false, |b| s <= b.quality) {
+            continue;
         }
-
-        let t = r[d.names.len()-1];
-        
-        // Get the estimate
-        match node.evaluate(&inputs) {
-            Some(e) => {        
-                let l = (t-e).powi(2);
-                //eprintln!("e {} t {} t-e {} inp: {:?}", e, t, t-e, &inputs);
-                y_d.push(l);
-            },
-            None => return Err(ScoreError::FailedEvaluation),
-        };
+        let errors:Vec<f64> = truth.iter().zip(estimate.iter()).
+            map(|(t, e)| f.case_error(*t, *e)).collect();
+        best = Some(Score{
+            quality:s,
+            class:class,
+            errors:Arc::new(errors),
+            metric:metric,
+            error:if f.higher_is_better() {-metric} else {metric},
+            size:node.count_nodes(),
+            depth:node.depth(),
+            inputs:node.inputs().len(),
+        });
     }
-
-    // y_d is the errors squared
-    let ss = y_d.iter().fold(0.0, | sum, &x| {
-        sum + x as f64
-    });
-    let rss = (ss / y_d.len().pow(2) as f64).sqrt();
-    // Must be increasing.  In this case maximum is 1, minimum aproaches 0
-    let s = 1.0/(rss + 1.0); 
-
-    match s.is_finite() {
-        true => Ok(Score{quality:s,}),
-        false => Err(ScoreError::NonFiniteSummation),
+    match best {
+        Some(s) => Ok(s),
+        None => Err(ScoreError::NonFiniteSummation),
     }
 }
 
@@ -0,0 +1,204 @@
+//! Selection algorithms.  Choosing the individuals of a generation
+//! that are parents of the next.  A selection is made from a
+//! generation and then used for crossover and mutation.  Set by the
+//! `selection` configuration key
+
+use rng::Stream;
+use std::sync::Arc;
+
+/// Choose an individual
+pub trait Selection {
+    /// The id of the individual selected
+    fn select(&self, rng:&mut Stream) -> usize;
+}
+
+/// The selection schemes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scheme {
+    Roulette,
+
+    // The size of the tournament
+    Tournament(usize),
+
+    Lexicase,
+
+    // NSGA-II.  Also changes which trees survive (see pareto.rs)
+    Pareto,
+}
+
+impl Scheme {
+    /// `roulette`, `tournament`, `lexicase` or `pareto`.
+    /// `tournament_size` is only used for tournaments
+    pub fn new_from_str(s:&str, tournament_size:usize) -> Scheme {
+        match s {
+            "roulette" => Scheme::Roulette,
+            "tournament" => {
+                if tournament_size < 1 {
+                    panic!("Tournament size must be at least one");
+                }
+                Scheme::Tournament(tournament_size)
+            },
+            "lexicase" => Scheme::Lexicase,
+            "pareto" => Scheme::Pareto,
+            _ => panic!("Unknown selection: {}", s),
+        }
+    }
+}
+
+/// https://en.wikipedia.org/wiki/Fitness_proportionate_selection
+/// Each individual has a slot on a wheel sized in proportion to its
+/// score.  See `Population::_make_wheel`
+pub struct Roulette {
+    // The id of each individual and the size of its slot.  The sizes
+    // sum to one
+    wheel:Vec<(usize, f64)>,
+}
+
+impl Roulette {
+    pub fn new(wheel:Vec<(usize, f64)>) -> Roulette {
+        // In order of id so the same seed makes the same selections
+        let mut wheel = wheel;
+        wheel.sort_by(|a, b| a.0.cmp(&b.0));
+        Roulette{wheel:wheel}
+    }
+}
+
+impl Selection for Roulette {
+    fn select(&self, rng:&mut Stream) -> usize {
+        let sel = rng.gen_range(0.0, 1.0);
+        // `sel` is the selector for the "roulette wheel".
+        let mut  acc = 0.0;
+
+        let mut ret:usize = 0;  // Index of selected individual
+        for (i, s) in self.wheel.iter() {
+            acc += s;
+            if acc > sel {
+                ret = *i;
+                break;
+            }
+        }
+        ret
+    }
+}
+
+/// https://en.wikipedia.org/wiki/Tournament_selection
+/// The best of a few individuals chosen at random.  Only the order
+/// of the scores matters, not their scale
+pub struct Tournament {
+    // The id and quality of each individual
+    candidates:Vec<(usize, f64)>,
+
+    // How many individuals are in a tournament
+    size:usize,
+}
+
+impl Tournament {
+    pub fn new(candidates:Vec<(usize, f64)>, size:usize) -> Tournament {
+        // In order of id so the same seed makes the same selections
+        let mut candidates = candidates;
+        candidates.sort_by(|a, b| a.0.cmp(&b.0));
+        Tournament{candidates:candidates, size:size}
+    }
+}
+
+impl Selection for Tournament {
+    fn select(&self, rng:&mut Stream) -> usize {
+        let mut ret = self.candidates[rng.gen_range(0, self.candidates.len())];
+        for _ in 1..self.size {
+            let c = self.candidates[rng.gen_range(0, self.candidates.len())];
+            if c.1 > ret.1 {
+                ret = c;
+            }
+        }
+        ret.0
+    }
+}
+
+/// Epsilon-lexicase selection.  The cases are taken in a random
+/// order.  For each case only the individuals with an error within
+/// epsilon of the best error for that case are kept, until one is
+/// left or there are no more cases.  Epsilon for a case is the
+/// median absolute deviation of the errors for that case.
+/// Individuals that are good on some cases but not others can be
+/// selected
+pub struct Lexicase {
+    // The id of each individual and its error for each case
+    candidates:Vec<(usize, Arc<Vec<f64>>)>,
+
+    // Epsilon for each case
+    epsilon:Vec<f64>,
+}
+
+// The median of some numbers.  Reorders them
+fn median(v:&mut Vec<f64>) -> f64 {
+    v.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let n = v.len();
+    if n == 0 {
+        0.0
+    }else if n % 2 == 1 {
+        v[n/2]
+    }else{
+        (v[n/2 - 1] + v[n/2]) / 2.0
+    }
+}
+
+impl Lexicase {
+    pub fn new(candidates:Vec<(usize, Arc<Vec<f64>>)>) -> Lexicase {
+        // In order of id so the same seed makes the same selections
+        let mut candidates = candidates;
+        candidates.sort_by(|a, b| a.0.cmp(&b.0));
+        let cases = candidates.iter().map(|c| c.1.len()).min().unwrap_or(0);
+        let epsilon = (0..cases).map(|j| {
+            let mut e:Vec<f64> = candidates.iter().map(|c| c.1[j]).collect();
+            let m = median(&mut e);
+            let mut d:Vec<f64> = e.iter().map(|x| (x - m).abs()).collect();
+            median(&mut d)
+        }).collect();
+        Lexicase{candidates:candidates, epsilon:epsilon}
+    }
+}
+
+impl Selection for Lexicase {
+    fn select(&self, rng:&mut Stream) -> usize {
+        let mut cases:Vec<usize> = (0..self.epsilon.len()).collect();
+        rng.shuffle(&mut cases[..]);
+        let mut pool:Vec<&(usize, Arc<Vec<f64>>)> = self.candidates.iter().collect();
+        for j in cases {
+            if pool.len() == 1 {
+                break;
+            }
+            let best = pool.iter().map(|c| c.1[j]).fold(f64::INFINITY, f64::min);
+            pool.retain(|c| c.1[j] <= best + self.epsilon[j]);
+        }
+        pool[rng.gen_range(0, pool.len())].0
+    }
+}
+
+/// The binary tournament of NSGA-II.  The individual in the better
+/// Pareto front wins.  In the same front the one in the less crowded
+/// neighbourhood wins
+pub struct Crowded {
+    // The id, front and crowding distance of each individual
+    candidates:Vec<(usize, usize, f64)>,
+}
+
+impl Crowded {
+    pub fn new(candidates:Vec<(usize, usize, f64)>) -> Crowded {
+        // In order of id so the same seed makes the same selections
+        let mut candidates = candidates;
+        candidates.sort_by(|a, b| a.0.cmp(&b.0));
+        Crowded{candidates:candidates}
+    }
+}
+
+impl Selection for Crowded {
+    fn select(&self, rng:&mut Stream) -> usize {
+        let a = self.candidates[rng.gen_range(0, self.candidates.len())];
+        let b = self.candidates[rng.gen_range(0, self.candidates.len())];
+        if b.1 < a.1 || (b.1 == a.1 && b.2 > a.2) {
+            b.0
+        }else{
+            a.0
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! Derivative free minimisation of a function of some numbers.  Used
+//! to tune the constants of the best trees (see
+//! `Population::_tune`).  A function that cannot be evaluated should
+//! return infinity
+
+use std::f64;
+
+/// The optimiser used
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Method {
+    NelderMead,
+    CoordinateDescent,
+}
+
+impl Method {
+    pub fn new_from_str(s:&str) -> Method {
+        match s {
+            "nelder_mead" => Method::NelderMead,
+            "coordinate_descent" => Method::CoordinateDescent,
+            _ => panic!("Unknown tuning method: {}", s),
+        }
+    }
+
+    /// Minimise `f` starting at `x0`, evaluating it at most
+    /// `evaluations` times.  Returns the best point found and the
+    /// value there
+    pub fn minimise<F:FnMut(&[f64]) -> f64>(&self, f:F, x0:&[f64],
+                                             evaluations:usize) -> (Vec<f64>, f64) {
+        match *self {
+            Method::NelderMead => nelder_mead(f, x0, evaluations),
+            Method::CoordinateDescent => coordinate_descent(f, x0, evaluations),
+        }
+    }
+}
+
+// The first step away from `x`.  In proportion to `x` unless it is
+// zero
+fn step(x:f64) -> f64 {
+    if x == 0.0 {0.1} else {0.1 * x.abs()}
+}
+
+/// The Nelder-Mead simplex method with the usual coefficients
+pub fn nelder_mead<F:FnMut(&[f64]) -> f64>(mut f:F, x0:&[f64],
+                                            evaluations:usize) -> (Vec<f64>, f64) {
+    let n = x0.len();
+    if n == 0 {
+        return (Vec::new(), f(x0));
+    }
+    let mut count = 0;
+    let mut eval = |x:&[f64], count:&mut usize| {
+        *count += 1;
+        let v = f(x);
+        if v.is_nan() {f64::INFINITY} else {v}
+    };
+
+    // The first simplex is `x0` and a step along each axis
+    let mut simplex:Vec<(Vec<f64>, f64)> = Vec::new();
+    let v = eval(x0, &mut count);
+    simplex.push((x0.to_vec(), v));
+    for i in 0..n {
+        let mut x = x0.to_vec();
+        x[i] += step(x[i]);
+        let v = eval(&x[..], &mut count);
+        simplex.push((x, v));
+    }
+
+    // A point on the line from `c` through `x`
+    let along = |c:&[f64], x:&[f64], t:f64| -> Vec<f64> {
+        c.iter().zip(x).map(|(c, x)| c + t * (x - c)).collect()
+    };
+
+    while count < evaluations {
+        simplex.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+
+        // The centre of all but the worst point
+        let mut c = vec![0.0; n];
+        for p in simplex[..n].iter() {
+            for i in 0..n {
+                c[i] += p.0[i] / n as f64;
+            }
+        }
+        let worst = simplex[n].clone();
+
+        let r = along(&c[..], &worst.0[..], -1.0);
+        let vr = eval(&r[..], &mut count);
+        if vr < simplex[0].1 {
+            // Better than the best.  Try going further
+            let e = along(&c[..], &worst.0[..], -2.0);
+            let ve = eval(&e[..], &mut count);
+            simplex[n] = if ve < vr {(e, ve)} else {(r, vr)};
+        }else if vr < simplex[n-1].1 {
+            simplex[n] = (r, vr);
+        }else{
+            // Contract towards the centre
+            let k = along(&c[..], &worst.0[..], 0.5);
+            let vk = eval(&k[..], &mut count);
+            if vk < worst.1 {
+                simplex[n] = (k, vk);
+            }else{
+                // Shrink everything towards the best point
+                let best = simplex[0].0.clone();
+                for p in simplex[1..].iter_mut() {
+                    let x = along(&best[..], &p.0[..], 0.5);
+                    let v = eval(&x[..], &mut count);
+                    *p = (x, v);
+                }
+            }
+        }
+    }
+    simplex.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+    simplex.swap_remove(0)
+}
+
+/// Move one value at a time up or down while that is better.  The
+/// steps are halved when neither is
+pub fn coordinate_descent<F:FnMut(&[f64]) -> f64>(mut f:F, x0:&[f64],
+                                                   evaluations:usize) -> (Vec<f64>, f64) {
+    let mut x = x0.to_vec();
+    let mut v = f(&x[..]);
+    if v.is_nan() {
+        v = f64::INFINITY;
+    }
+    if x.is_empty() {
+        return (x, v);
+    }
+    let mut steps:Vec<f64> = x.iter().map(|x| step(*x)).collect();
+    let mut count = 1;
+    while count < evaluations {
+        for i in 0..x.len() {
+            let mut moved = false;
+            for d in vec![steps[i], -steps[i]] {
+                let mut y = x.clone();
+                y[i] += d;
+                let w = f(&y[..]);
+                count += 1;
+                if w < v {
+                    x = y;
+                    v = w;
+                    moved = true;
+                    break;
+                }
+            }
+            if !moved {
+                steps[i] /= 2.0;
+            }
+        }
+    }
+    (x, v)
+}
@@ -0,0 +1,121 @@
+//! Units of inputs and of the values of trees.  Used to stop trees
+//! combining inputs in ways that make no physical sense, like adding
+//! a weight to a length.
+
+//! A unit is written as base units with exponents separated by `*`.
+//! E.g. `g`, `mm^2`, `kg*m^-3`.  `1` is dimensionless.
+
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A unit.  The exponent of each base unit.  Dimensionless if there
+/// are none
+pub struct Unit {
+    dims:BTreeMap<String, i32>,
+}
+
+/// The unit of a tree.  `None` for a constant or an input with no
+/// unit.  Where two values must have the same unit it matches any
+/// unit.  In a product it is a dimensionless scalar
+pub type UnitType = Option<Unit>;
+
+impl Unit {
+    pub fn dimensionless() -> Unit {
+        Unit{dims:BTreeMap::new()}
+    }
+
+    /// Parse a unit.  E.g. `kg*m^-3`
+    pub fn new_from_str(s:&str) -> Unit {
+        let mut ret = Unit::dimensionless();
+        for b in s.trim().split('*') {
+            let b = b.trim();
+            if b == "" || b == "1" {
+                continue;
+            }
+            let mut iter = b.split('^');
+            let name = iter.next().unwrap().trim().to_string();
+            let exp = match iter.next() {
+                Some(e) => e.trim().parse::<i32>().
+                    expect(format!("Invalid unit: {}", s).as_str()),
+                None => 1,
+            };
+            *ret.dims.entry(name).or_insert(0) += exp;
+        }
+        ret.dims.retain(|_, e| *e != 0);
+        ret
+    }
+
+    pub fn is_dimensionless(&self) -> bool {
+        self.dims.len() == 0
+    }
+
+    /// The unit of the product of values in `self` and `other`
+    pub fn multiply(&self, other:&Unit) -> Unit {
+        let mut ret = self.clone();
+        for (k, e) in other.dims.iter() {
+            *ret.dims.entry(k.clone()).or_insert(0) += e;
+        }
+        ret.dims.retain(|_, e| *e != 0);
+        ret
+    }
+
+    /// The unit of one over a value in `self`
+    pub fn invert(&self) -> Unit {
+        Unit{dims:self.dims.iter().map(|(k, e)| (k.clone(), -e)).collect()}
+    }
+
+    /// The unit of the square root of a value in `self`.  `None` if
+    /// a exponent is odd
+    pub fn sqrt(&self) -> Option<Unit> {
+        if self.dims.values().all(|e| e % 2 == 0) {
+            Some(Unit{dims:self.dims.iter().map(|(k, e)| (k.clone(), e/2)).collect()})
+        }else{
+            None
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn to_string(&self) -> String {
+        if self.is_dimensionless() {
+            "1".to_string()
+        }else{
+            let v:Vec<String> = self.dims.iter().map(|(k, e)| {
+                if *e == 1 {
+                    k.clone()
+                }else{
+                    format!("{}^{}", k, e)
+                }
+            }).collect();
+            v.join("*")
+        }
+    }
+}
+
+/// The unit of two values that must have the same unit.  `Err` if
+/// they do not
+pub fn same(a:&UnitType, b:&UnitType) -> Result<UnitType, String> {
+    match (a, b) {
+        (&Some(ref x), &Some(ref y)) => if x == y {
+            Ok(Some(x.clone()))
+        }else{
+            Err(format!("{} and {}", x.to_string(), y.to_string()))
+        },
+        (&Some(ref x), &None) => Ok(Some(x.clone())),
+        (&None, _) => Ok(b.clone()),
+    }
+}
+
+/// The unit of the product of two values.  A value with no unit
+/// scales the other without changing its unit
+pub fn product(a:&UnitType, b:&UnitType) -> UnitType {
+    match (a, b) {
+        (&Some(ref x), &Some(ref y)) => Some(x.multiply(y)),
+        (&Some(ref x), &None) => Some(x.clone()),
+        (&None, _) => b.clone(),
+    }
+}
+
+/// A value must be dimensionless (or have any unit)
+pub fn dimensionless(a:&UnitType) -> Result<UnitType, String> {
+    same(a, &Some(Unit::dimensionless()))
+}